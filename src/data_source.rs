@@ -0,0 +1,92 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+};
+
+use serde_json::Value;
+
+use crate::{
+    parse_to_hashmap::flatten,
+    value::{resolve_path, value_from_str, value_to_str},
+};
+
+/// Somewhere the values of a template are looked up, one key at a time.
+/// This avoids building a hashmap of every value when the template only needs a few of them.
+pub trait DataSource {
+    /// The value of a key, or `None` when the key doesn't exist.
+    fn get(&self, key: &str) -> Option<Cow<'_, str>>;
+    /// The typed value of a key, used by the blocks to check what the value really is.
+    /// By default, the text of the key is read back as `null`, a boolean or a number when it's written as one, or as a string otherwise.
+    fn get_value(&self, key: &str) -> Option<Cow<'_, Value>> {
+        self.get(key)
+            .map(|value| Cow::Owned(value_from_str(&value)))
+    }
+    /// Every key of the source, used to suggest close keys when one is missing.
+    /// Sources that can't list their keys don't have to.
+    fn keys(&self) -> Vec<String> {
+        Vec::new()
+    }
+    /// The number of items of every array whose items are flattened into their own keys (`items.0.name`), by path.
+    /// The `{{#each}}` blocks use it when `get_value` doesn't give an array, asking for it at most once per rendering.
+    /// By default, the arrays are found in the keys of the source.
+    fn array_lens(&self) -> HashMap<String, usize> {
+        let mut lens = HashMap::new();
+        for key in self.keys() {
+            for (dot, _) in key.match_indices('.') {
+                let segment = key[dot + 1..].split('.').next().unwrap_or_default();
+                if let Ok(index) = segment.parse::<usize>() {
+                    let len = lens.entry(key[..dot].to_string()).or_insert(0);
+                    *len = (*len).max(index + 1);
+                }
+            }
+        }
+        lens
+    }
+}
+
+impl DataSource for HashMap<String, String> {
+    fn get(&self, key: &str) -> Option<Cow<'_, str>> {
+        HashMap::get(self, key).map(|value| Cow::Borrowed(value.as_str()))
+    }
+    fn keys(&self) -> Vec<String> {
+        HashMap::keys(self).cloned().collect()
+    }
+}
+
+impl DataSource for BTreeMap<String, String> {
+    fn get(&self, key: &str) -> Option<Cow<'_, str>> {
+        BTreeMap::get(self, key).map(|value| Cow::Borrowed(value.as_str()))
+    }
+    fn keys(&self) -> Vec<String> {
+        BTreeMap::keys(self).cloned().collect()
+    }
+}
+
+/// Keys are dotted paths walked lazily inside the tree, strings are given as is while any other value is given as `JSON`.
+impl DataSource for Value {
+    fn get(&self, key: &str) -> Option<Cow<'_, str>> {
+        resolve_path(self, key).map(value_to_str)
+    }
+    fn get_value(&self, key: &str) -> Option<Cow<'_, Value>> {
+        resolve_path(self, key).map(Cow::Borrowed)
+    }
+    fn keys(&self) -> Vec<String> {
+        let mut datas = HashMap::new();
+        flatten("", self, &mut datas);
+        datas.into_keys().collect()
+    }
+    /// Arrays are given as is by `get_value`.
+    fn array_lens(&self) -> HashMap<String, usize> {
+        HashMap::new()
+    }
+}
+
+/// Any closure giving the value of a key.
+impl<F> DataSource for F
+where
+    F: Fn(&str) -> Option<String>,
+{
+    fn get(&self, key: &str) -> Option<Cow<'_, str>> {
+        self(key).map(Cow::Owned)
+    }
+}
@@ -0,0 +1,89 @@
+use crate::errors::StringTemplaterError;
+
+/// The delimiters opening and closing the tags of a template, `{{` and `}}` by default.
+/// Nested templates repeat the last character of the opening delimiter and the first character of the closing one, such as `{{{key}}}` or `<%%key%%>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delimiters {
+    open: String,
+    close: String,
+    /// The character added after the opening delimiter to open a nested template.
+    nested_open: char,
+    /// The character added before the closing delimiter to close a nested template.
+    nested_close: char,
+}
+
+/// The characters with a meaning inside the tags, which would clash with the delimiters.
+/// Keys, loop variables and filter calls also use the ASCII alphanumerics.
+const TAG_SYNTAX: &str = "|*?&!#/-\"_.@(),";
+
+impl Default for Delimiters {
+    fn default() -> Self {
+        Delimiters {
+            open: "{{".to_string(),
+            close: "}}".to_string(),
+            nested_open: '{',
+            nested_close: '}',
+        }
+    }
+}
+
+impl Delimiters {
+    /// Use other delimiters for the tags, such as `<%` and `%>` or `${` and `}`.
+    /// Delimiters can't be empty, contain whitespaces, ASCII alphanumerics or the characters of the tag syntax (`|*?&!#/-"_.@(),`), or start with the escape character `\`.
+    pub fn new(open: &str, close: &str) -> Result<Self, StringTemplaterError> {
+        let valid = |delimiter: &str| {
+            !delimiter.starts_with('\\')
+                && !delimiter.contains(|c: char| {
+                    c.is_whitespace() || c.is_ascii_alphanumeric() || TAG_SYNTAX.contains(c)
+                })
+        };
+        match (open.chars().last(), close.chars().next()) {
+            (Some(nested_open), Some(nested_close)) if valid(open) && valid(close) => {
+                Ok(Delimiters {
+                    open: open.to_string(),
+                    close: close.to_string(),
+                    nested_open,
+                    nested_close,
+                })
+            }
+            _ => Err(StringTemplaterError::InvalidDelimiters {
+                open: open.to_string(),
+                close: close.to_string(),
+            }),
+        }
+    }
+    /// The delimiter opening a tag.
+    pub fn open(&self) -> &str {
+        &self.open
+    }
+    /// The delimiter closing a tag.
+    pub fn close(&self) -> &str {
+        &self.close
+    }
+    pub(crate) fn nested_open(&self) -> char {
+        self.nested_open
+    }
+    pub(crate) fn nested_close(&self) -> char {
+        self.nested_close
+    }
+    /// The text closing a tag, with the extra character of nested templates.
+    pub(crate) fn closing(&self, nested: bool) -> String {
+        if nested {
+            format!("{}{}", self.nested_close, self.close)
+        } else {
+            self.close.clone()
+        }
+    }
+    /// Whether a `\` written between `before` and `after` escapes the character after it.
+    /// It escapes a delimiter starting right after it (`\<%`) or split by it (`<\%`), and any brace or `\` with the default delimiters.
+    pub(crate) fn escapes(&self, before: &str, after: &str) -> bool {
+        if *self == Delimiters::default() && after.starts_with(['{', '}', '\\']) {
+            return true;
+        }
+        [&self.open, &self.close].into_iter().any(|delimiter| {
+            delimiter.char_indices().any(|(i, _)| {
+                before.ends_with(&delimiter[..i]) && after.starts_with(&delimiter[i..])
+            })
+        })
+    }
+}
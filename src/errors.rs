@@ -1,10 +1,195 @@
+use crate::{limit::Limit, span::Span};
+
 /// Any errors that might occurs while generating or parsing the string template.
+///
+/// Errors coming from a template tag carry the `span` of the tag inside the template it was found in.
+/// When the tag belongs to a nested template (`{{{key}}}`), `expanded_from` lists the spans of the tags that expanded it, from the outermost to the innermost.
 #[derive(Debug, Clone, PartialEq, thiserror::Error)]
 pub enum StringTemplaterError {
-    #[error("Missing curvy bracket: `{0}`")]
-    MissingCurvyBracket(String),
-    #[error("Unknown field: `{0}`")]
-    UnknownField(String),
+    /// A tag isn't closed by as many curvy brackets `}` as it was opened with.
+    #[error(
+        "Missing curvy bracket: `Missing {} curvy bracket `}}` around `{key}`.`",
+        count_to_word(.expected - .found)
+    )]
+    MissingCurvyBracket {
+        /// The number of closing curvy brackets the tag needs.
+        expected: usize,
+        /// The number of closing curvy brackets actually found.
+        found: usize,
+        /// The key written inside the tag.
+        key: String,
+        span: Span,
+        expanded_from: Vec<Span>,
+    },
+    /// A key used by a tag doesn't exist in the datas.
+    #[error("Unknown field: `The field `{key}` does not exist in the hashmap.`")]
+    UnknownField {
+        /// The key that couldn't be found.
+        key: String,
+        /// The key written in the tag when the missing key was reached through mirrors.
+        via_mirror_of: Option<String>,
+        /// Up to three existing keys close to the missing one, from the closest.
+        /// Boxed to keep the errors small.
+        suggestions: Box<[String]>,
+        span: Span,
+        expanded_from: Vec<Span>,
+    },
+    /// A tag isn't written the way its syntax expects.
+    #[error("Invalid tag: `{tag}`")]
+    InvalidTag {
+        /// The whole tag as written in the template.
+        tag: String,
+        span: Span,
+        expanded_from: Vec<Span>,
+    },
+    /// A tag uses a filter that doesn't exist.
+    #[error("Unknown filter: `{name}`")]
+    UnknownFilter {
+        /// The name of the filter.
+        name: String,
+        span: Span,
+        expanded_from: Vec<Span>,
+    },
+    /// A tag gives a filter arguments it doesn't accept.
+    #[error("Invalid filter arguments: `{name}`")]
+    InvalidFilterArguments {
+        /// The name of the filter.
+        name: String,
+        span: Span,
+        expanded_from: Vec<Span>,
+    },
+    /// A custom filter returned an error.
+    #[error("Filter error: `{name}`: `{message}`")]
+    FilterError {
+        /// The name of the filter.
+        name: String,
+        /// The error returned by the filter.
+        message: String,
+        span: Span,
+        expanded_from: Vec<Span>,
+    },
+    /// A block is never closed by its closing tag.
+    #[error(
+        "Unclosed block: `The block `{{{{#{block}}}}}` is never closed by `{{{{/{block}}}}}`.`"
+    )]
+    UnclosedBlock {
+        /// The name of the block, such as `if`.
+        block: String,
+        /// The location of the tag opening the block.
+        span: Span,
+        expanded_from: Vec<Span>,
+    },
+    /// Nested templates expand each other in a cycle, or deeper than the options allow.
+    #[error("Recursion limit: `{}`", .chain.join(" -> "))]
+    RecursionLimit {
+        /// The keys of the nested templates being expanded, from the first key of the cycle or from the outermost one.
+        chain: Vec<String>,
+        span: Span,
+        expanded_from: Vec<Span>,
+    },
+    /// The rendering went beyond one of the limits of the options, so it was aborted.
+    #[error("Limit exceeded: `The template goes {limit}.`")]
+    LimitExceeded { limit: Limit },
+    /// Delimiters that can't be used for the tags of a template.
+    #[error("Invalid delimiters: `{open}` and `{close}`")]
+    InvalidDelimiters { open: String, close: String },
     #[error("Serialize error: `{0}`")]
     SerializeError(String),
+    /// The sink the template was rendered into refused the output.
+    #[error("Write error: `{message}`")]
+    WriteError {
+        kind: std::io::ErrorKind,
+        message: String,
+    },
+}
+
+impl StringTemplaterError {
+    /// The span of the tag that caused the error, if any.
+    pub fn span(&self) -> Option<Span> {
+        self.location().map(|(span, _)| span)
+    }
+    /// The span of the tag in the outermost template that led to the error.
+    pub(crate) fn root_span(&self) -> Option<Span> {
+        self.location()
+            .map(|(span, expanded_from)| *expanded_from.first().unwrap_or(&span))
+    }
+    /// Record that the error happened inside nested templates expanded by the tags at `spans`.
+    pub(crate) fn with_expansion(mut self, spans: &[Span]) -> Self {
+        if let Some(expanded_from) = self.expanded_from_mut() {
+            expanded_from.splice(0..0, spans.iter().copied());
+        }
+        self
+    }
+    fn location(&self) -> Option<(Span, &[Span])> {
+        match self {
+            StringTemplaterError::MissingCurvyBracket {
+                span,
+                expanded_from,
+                ..
+            }
+            | StringTemplaterError::UnknownField {
+                span,
+                expanded_from,
+                ..
+            }
+            | StringTemplaterError::InvalidTag {
+                span,
+                expanded_from,
+                ..
+            }
+            | StringTemplaterError::UnknownFilter {
+                span,
+                expanded_from,
+                ..
+            }
+            | StringTemplaterError::InvalidFilterArguments {
+                span,
+                expanded_from,
+                ..
+            }
+            | StringTemplaterError::FilterError {
+                span,
+                expanded_from,
+                ..
+            }
+            | StringTemplaterError::UnclosedBlock {
+                span,
+                expanded_from,
+                ..
+            }
+            | StringTemplaterError::RecursionLimit {
+                span,
+                expanded_from,
+                ..
+            } => Some((*span, expanded_from)),
+            StringTemplaterError::LimitExceeded { .. }
+            | StringTemplaterError::InvalidDelimiters { .. }
+            | StringTemplaterError::SerializeError(_)
+            | StringTemplaterError::WriteError { .. } => None,
+        }
+    }
+    fn expanded_from_mut(&mut self) -> Option<&mut Vec<Span>> {
+        match self {
+            StringTemplaterError::MissingCurvyBracket { expanded_from, .. }
+            | StringTemplaterError::UnknownField { expanded_from, .. }
+            | StringTemplaterError::InvalidTag { expanded_from, .. }
+            | StringTemplaterError::UnknownFilter { expanded_from, .. }
+            | StringTemplaterError::InvalidFilterArguments { expanded_from, .. }
+            | StringTemplaterError::FilterError { expanded_from, .. }
+            | StringTemplaterError::UnclosedBlock { expanded_from, .. }
+            | StringTemplaterError::RecursionLimit { expanded_from, .. } => Some(expanded_from),
+            StringTemplaterError::LimitExceeded { .. }
+            | StringTemplaterError::InvalidDelimiters { .. }
+            | StringTemplaterError::SerializeError(_)
+            | StringTemplaterError::WriteError { .. } => None,
+        }
+    }
+}
+
+fn count_to_word(count: usize) -> &'static str {
+    match count {
+        1 => "one",
+        2 => "two",
+        _ => "three",
+    }
 }
@@ -0,0 +1,60 @@
+use std::borrow::Cow;
+
+/// The escaping applied to every value written by a tag, depending on where the output ends up.
+/// A tag opts out of the escaping using `{{&key}}`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Escaping {
+    /// Values are written as is.
+    #[default]
+    None,
+    /// `&`, `<`, `>`, `"` and `'` are replaced by their `HTML` entities.
+    Html,
+    /// Values are escaped to be written inside a `JSON` string, without the surrounding double quotes.
+    JsonString,
+    /// Every byte but the unreserved characters (`A-Z`, `a-z`, `0-9`, `-`, `_`, `.` and `~`) is percent encoded.
+    UrlComponent,
+    /// Values are quoted between single quotes, to be used as a single argument of a `POSIX` shell.
+    PosixShell,
+}
+
+impl Escaping {
+    /// Escape a value.
+    pub fn escape<'v>(&self, value: &'v str) -> Cow<'v, str> {
+        match self {
+            Escaping::None => Cow::Borrowed(value),
+            Escaping::Html => {
+                if !value.contains(['&', '<', '>', '"', '\'']) {
+                    return Cow::Borrowed(value);
+                }
+                let mut escaped = String::with_capacity(value.len());
+                for c in value.chars() {
+                    match c {
+                        '&' => escaped.push_str("&amp;"),
+                        '<' => escaped.push_str("&lt;"),
+                        '>' => escaped.push_str("&gt;"),
+                        '"' => escaped.push_str("&quot;"),
+                        '\'' => escaped.push_str("&#x27;"),
+                        _ => escaped.push(c),
+                    }
+                }
+                Cow::Owned(escaped)
+            }
+            Escaping::JsonString => {
+                let quoted = serde_json::Value::from(value).to_string();
+                Cow::Owned(quoted[1..quoted.len() - 1].to_string())
+            }
+            Escaping::UrlComponent => {
+                let mut escaped = String::with_capacity(value.len());
+                for byte in value.bytes() {
+                    if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~') {
+                        escaped.push(byte as char);
+                    } else {
+                        escaped.push_str(&format!("%{:02X}", byte));
+                    }
+                }
+                Cow::Owned(escaped)
+            }
+            Escaping::PosixShell => Cow::Owned(format!("'{}'", value.replace('\'', "'\\''"))),
+        }
+    }
+}
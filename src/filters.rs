@@ -0,0 +1,124 @@
+use std::{collections::HashMap, fmt, sync::Arc};
+
+/// An argument given to a filter, such as `10` in `truncate(10)` or `"a"` in `replace("a", "b")`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Arg {
+    /// A literal written between double quotes.
+    String(String),
+    /// A number.
+    Number(f64),
+}
+
+impl Arg {
+    /// The text of a string argument.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Arg::String(s) => Some(s),
+            Arg::Number(_) => None,
+        }
+    }
+    /// The value of a number argument that is a positive integer.
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            Arg::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as usize),
+            _ => None,
+        }
+    }
+}
+
+/// A custom filter, turning a value and its arguments into a new value or an error message.
+type CustomFilter = Arc<dyn Fn(&str, &[Arg]) -> Result<String, String> + Send + Sync>;
+
+/// The custom filters available to the templates, on top of the built-in ones.
+#[derive(Default, Clone)]
+pub struct Filters {
+    custom_filters: HashMap<String, CustomFilter>,
+}
+
+impl Filters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Register a filter under a name, replacing any filter of the same name including the built-in ones.
+    /// The error returned by the filter is reported as a `StringTemplaterError::FilterError`.
+    pub fn register<F, E>(&mut self, name: &str, filter: F)
+    where
+        F: Fn(&str, &[Arg]) -> Result<String, E> + Send + Sync + 'static,
+        E: fmt::Display,
+    {
+        self.custom_filters.insert(
+            name.to_string(),
+            Arc::new(move |value, args| filter(value, args).map_err(|err| err.to_string())),
+        );
+    }
+    /// Apply the filter of this name, whether it's a custom or a built-in one.
+    pub(crate) fn apply(
+        &self,
+        name: &str,
+        value: &str,
+        args: &[Arg],
+    ) -> Result<String, FilterFailure> {
+        match self.custom_filters.get(name) {
+            Some(filter) => filter(value, args).map_err(FilterFailure::Failed),
+            None => apply_builtin_filter(name, value, args),
+        }
+    }
+}
+
+impl fmt::Debug for Filters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names = self.custom_filters.keys().collect::<Vec<_>>();
+        names.sort();
+        f.debug_struct("Filters")
+            .field("custom_filters", &names)
+            .finish()
+    }
+}
+
+/// The reasons a filter can't be applied.
+pub(crate) enum FilterFailure {
+    /// No filter has this name.
+    Unknown,
+    /// The filter doesn't accept these arguments.
+    InvalidArguments,
+    /// The filter returned an error.
+    Failed(String),
+}
+
+/// Apply one of the built-in filters to a value.
+fn apply_builtin_filter(name: &str, value: &str, args: &[Arg]) -> Result<String, FilterFailure> {
+    match (name, args) {
+        ("upper", []) => Ok(value.to_uppercase()),
+        ("lower", []) => Ok(value.to_lowercase()),
+        ("capitalize", []) => {
+            let mut chars = value.chars();
+            Ok(match chars.next() {
+                Some(first) => first
+                    .to_uppercase()
+                    .chain(chars.as_str().to_lowercase().chars())
+                    .collect(),
+                None => String::new(),
+            })
+        }
+        ("trim", []) => Ok(value.trim().to_string()),
+        ("truncate", [length]) => match length.as_usize() {
+            Some(length) => Ok(value.chars().take(length).collect()),
+            None => Err(FilterFailure::InvalidArguments),
+        },
+        ("replace", [from, to]) => match (from.as_str(), to.as_str()) {
+            (Some(from), Some(to)) if !from.is_empty() => Ok(value.replace(from, to)),
+            _ => Err(FilterFailure::InvalidArguments),
+        },
+        ("len", []) => Ok(value.chars().count().to_string()),
+        ("default", [default]) => match default.as_str() {
+            Some(default) if value.is_empty() => Ok(default.to_string()),
+            Some(_) => Ok(value.to_string()),
+            None => Err(FilterFailure::InvalidArguments),
+        },
+        (
+            "upper" | "lower" | "capitalize" | "trim" | "truncate" | "replace" | "len" | "default",
+            _,
+        ) => Err(FilterFailure::InvalidArguments),
+        _ => Err(FilterFailure::Unknown),
+    }
+}
@@ -1,12 +1,14 @@
-use crate::{errors::StringTemplaterError, generate_with_options, StringTemplaterOptions};
-use std::collections::HashMap;
+use crate::{
+    data_source::DataSource, errors::StringTemplaterError, generate_with_options,
+    StringTemplaterOptions,
+};
 
-/// Inject values of a hashmap based on their field names.
+/// Inject values of a data source, such as a hashmap, based on their field names.
 /// Datas are injected on a specific string interpolation using the pattern `{{key_name}}`.
 /// This allow a simple navigation around the datas.
-pub fn generate(
+pub fn generate<D: DataSource + ?Sized>(
     template_str: &str,
-    data: &HashMap<String, String>,
+    data: &D,
 ) -> Result<String, StringTemplaterError> {
     generate_with_options(template_str, data, &StringTemplaterOptions::default())
 }
@@ -0,0 +1,16 @@
+use crate::{
+    data_source::DataSource, errors::StringTemplaterError, StringTemplaterOptions, Template,
+};
+use std::fmt;
+
+/// Inject values of a data source, such as a hashmap, based on their field names, writing the output straight into `out`.
+/// Datas are injected on a specific string interpolation using the pattern `{{key_name}}`.
+/// When an error occurs, the output written so far stays in `out`.
+pub fn generate_to_fmt<D: DataSource + ?Sized>(
+    template_str: &str,
+    data: &D,
+    option: &StringTemplaterOptions,
+    out: &mut impl fmt::Write,
+) -> Result<(), StringTemplaterError> {
+    Template::compile_with_options(template_str, option)?.render_to_fmt(data, option, out)
+}
@@ -0,0 +1,36 @@
+use std::{fmt, io};
+
+use crate::errors::StringTemplaterError;
+
+/// Let the renderer write into an `io::Write` sink, keeping the IO error that stopped it.
+pub(crate) struct IoWriter<'a, W: io::Write> {
+    inner: &'a mut W,
+    error: Option<io::Error>,
+}
+
+impl<'a, W: io::Write> IoWriter<'a, W> {
+    pub(crate) fn new(inner: &'a mut W) -> Self {
+        IoWriter { inner, error: None }
+    }
+    /// Replace a write error of the renderer by the IO error behind it.
+    pub(crate) fn restore_error(&mut self, err: StringTemplaterError) -> StringTemplaterError {
+        match (err, self.error.take()) {
+            (StringTemplaterError::WriteError { .. }, Some(io_error)) => {
+                StringTemplaterError::WriteError {
+                    kind: io_error.kind(),
+                    message: io_error.to_string(),
+                }
+            }
+            (err, _) => err,
+        }
+    }
+}
+
+impl<W: io::Write> fmt::Write for IoWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|err| {
+            self.error = Some(err);
+            fmt::Error
+        })
+    }
+}
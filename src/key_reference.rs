@@ -0,0 +1,39 @@
+use crate::span::Span;
+
+/// A key referenced by a tag of a template.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyReference {
+    /// The name of the key written in the tag.
+    pub key: String,
+    /// The way the tag uses the value of the key.
+    pub kind: KeyKind,
+    /// Whether the key is only looked up when the keys before it in the tag don't exist (`{{key ?? fallback}}`).
+    pub fallback: bool,
+    /// Whether the template still renders when the key and its fallbacks don't exist, such as a tag with a default literal (`{{key ?? "default"}}`) or the key of a block.
+    pub optional: bool,
+    /// The location of the tag inside the template.
+    pub span: Span,
+}
+
+/// The way a tag uses the value of a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyKind {
+    /// The value is written as is: `{{key}}`.
+    Plain,
+    /// The value is used as the name of another key, `depth` times: `{{*key}}`.
+    Mirror { depth: usize },
+    /// The value is used as a template, after applying `mirror_depth` mirrors: `{{{key}}}` or `{{{*key}}}`.
+    Nested { mirror_depth: usize },
+    /// The value decides whether a conditional block is rendered: `{{#if key}}`.
+    Condition,
+    /// The value is an array walked by a loop block: `{{#each key as item}}`.
+    Loop,
+}
+
+impl KeyReference {
+    /// Whether the value of the key leads to more keys that are only known once the datas are available.
+    /// This is the case for mirrors and nested templates, whose keys can't be listed before rendering.
+    pub fn reaches_dynamic_keys(&self) -> bool {
+        matches!(self.kind, KeyKind::Mirror { .. } | KeyKind::Nested { .. })
+    }
+}
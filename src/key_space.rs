@@ -0,0 +1,115 @@
+use std::collections::{HashMap, HashSet};
+
+/// Every key that could exist in the datas produced by a sample, with array indices left free.
+pub(crate) struct KeySpace {
+    shapes: HashSet<Shape>,
+}
+
+#[derive(PartialEq, Eq, Hash)]
+struct Shape {
+    segments: Vec<Segment>,
+    /// Whether any key deeper than the shape may also exist, because the sample had no data there.
+    open: bool,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Segment {
+    Field(String),
+    Index,
+}
+
+impl KeySpace {
+    /// The key space of a serialized sample, following the same paths as `parse_to_hashmap`.
+    /// `null` values, empty arrays and empty objects might hold anything, so every key below them is accepted.
+    pub(crate) fn from_value(value: &serde_json::Value) -> Self {
+        let mut key_space = KeySpace {
+            shapes: HashSet::new(),
+        };
+        key_space.insert_value(&mut Vec::new(), value);
+        key_space
+    }
+    /// The key space of already flattened datas, where every number in a path is taken as an array index.
+    pub(crate) fn from_hashmap(data: &HashMap<String, String>) -> Self {
+        let shapes = data
+            .keys()
+            .map(|key| Shape {
+                segments: key
+                    .split('.')
+                    .map(|segment| {
+                        if is_index(segment) {
+                            Segment::Index
+                        } else {
+                            Segment::Field(segment.to_string())
+                        }
+                    })
+                    .collect(),
+                open: false,
+            })
+            .collect();
+        KeySpace { shapes }
+    }
+    fn insert_value(&mut self, prefix: &mut Vec<Segment>, value: &serde_json::Value) {
+        match value {
+            serde_json::Value::Object(obj) if !obj.is_empty() => {
+                for (k, v) in obj {
+                    prefix.push(Segment::Field(k.clone()));
+                    self.insert_value(prefix, v);
+                    prefix.pop();
+                }
+            }
+            serde_json::Value::Array(arr) if !arr.is_empty() => {
+                prefix.push(Segment::Index);
+                for v in arr {
+                    self.insert_value(prefix, v);
+                }
+                prefix.pop();
+            }
+            _ => {
+                let open = matches!(
+                    value,
+                    serde_json::Value::Null
+                        | serde_json::Value::Object(_)
+                        | serde_json::Value::Array(_)
+                );
+                self.shapes.insert(Shape {
+                    segments: prefix.clone(),
+                    open,
+                });
+            }
+        }
+    }
+    /// Whether the key could exist in the datas.
+    pub(crate) fn contains(&self, key: &str) -> bool {
+        self.shapes.iter().any(|shape| shape.matches(key))
+    }
+}
+
+impl Shape {
+    fn matches(&self, key: &str) -> bool {
+        let mut rest = key;
+        for (i, segment) in self.segments.iter().enumerate() {
+            if i > 0 {
+                match rest.strip_prefix('.') {
+                    Some(next) => rest = next,
+                    None => return false,
+                }
+            }
+            let matched = match segment {
+                Segment::Field(field) => rest.strip_prefix(field.as_str()),
+                Segment::Index => {
+                    let end = rest.find('.').unwrap_or(rest.len());
+                    is_index(&rest[..end]).then(|| &rest[end..])
+                }
+            };
+            match matched {
+                Some(next) => rest = next,
+                None => return false,
+            }
+        }
+        rest.is_empty() || (self.open && (self.segments.is_empty() || rest.starts_with('.')))
+    }
+}
+
+fn is_index(segment: &str) -> bool {
+    !segment.is_empty() && segment.bytes().all(|b| b.is_ascii_digit())
+}
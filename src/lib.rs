@@ -1,26 +1,73 @@
+mod data_source;
+mod delimiters;
 mod errors;
+mod escaping;
+mod filters;
 mod generate;
+mod generate_to_fmt;
+mod generate_to_io;
 mod generate_with_options;
+mod io_writer;
+mod key_reference;
+mod key_space;
+mod limit;
+mod missing_key_policy;
 mod options;
 mod parse;
 mod parse_to_hashmap;
+mod parse_to_value;
 mod parse_with_options;
+mod span;
+mod suggestions;
+mod template;
 mod template_builder;
+mod template_parser;
+mod template_renderer;
+mod validate;
+mod validate_hashmap;
+mod validate_hashmap_with_delimiters;
+mod validate_with_delimiters;
+mod value;
 
+pub use crate::data_source::DataSource;
+pub use crate::delimiters::Delimiters;
 pub use crate::errors::StringTemplaterError;
+pub use crate::escaping::Escaping;
+pub use crate::filters::{Arg, Filters};
 pub use crate::generate::generate;
+pub use crate::generate_to_fmt::generate_to_fmt;
+pub use crate::generate_to_io::generate_to_io;
 pub use crate::generate_with_options::generate_with_options;
+pub use crate::key_reference::{KeyKind, KeyReference};
+pub use crate::limit::Limit;
+pub use crate::missing_key_policy::MissingKeyPolicy;
 pub use crate::options::{OverrideMessage, StringTemplaterOptions};
 pub use crate::parse::parse;
 pub use crate::parse_to_hashmap::parse_to_hashmap;
+pub use crate::parse_to_value::parse_to_value;
 pub use crate::parse_with_options::parse_with_options;
+pub use crate::span::Span;
+pub use crate::template::{EachBlock, FilterCall, IfBlock, IfBranch, Node, Tag, Template};
 pub use crate::template_builder::TemplateBuilder;
+pub use crate::validate::validate;
+pub use crate::validate_hashmap::validate_hashmap;
+pub use crate::validate_hashmap_with_delimiters::validate_hashmap_with_delimiters;
+pub use crate::validate_with_delimiters::validate_with_delimiters;
+pub use serde_json::Value;
 
 #[cfg(test)]
 mod test {
     use crate::*;
     use serde::Serialize;
-    use std::collections::HashMap;
+    use std::{
+        borrow::Cow,
+        collections::{BTreeMap, HashMap},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, OnceLock,
+        },
+        thread,
+    };
 
     #[derive(Debug, Serialize)]
     struct A {
@@ -156,9 +203,18 @@ mod test {
         };
         assert_eq!(
             result,
-            StringTemplaterError::MissingCurvyBracket(
-                "Missing one curvy bracket `}` around `name`.".to_string()
-            )
+            StringTemplaterError::MissingCurvyBracket {
+                expected: 2,
+                found: 1,
+                key: "name".to_string(),
+                span: Span {
+                    start: 6,
+                    end: 13,
+                    line: 1,
+                    column: 7,
+                },
+                expanded_from: Vec::new(),
+            }
         )
     }
 
@@ -175,9 +231,18 @@ mod test {
         };
         assert_eq!(
             result,
-            StringTemplaterError::MissingCurvyBracket(
-                "Missing two curvy bracket `}` around `name! How're you today?`.".to_string()
-            )
+            StringTemplaterError::MissingCurvyBracket {
+                expected: 2,
+                found: 0,
+                key: "name! How're you today?".to_string(),
+                span: Span {
+                    start: 6,
+                    end: 31,
+                    line: 1,
+                    column: 7,
+                },
+                expanded_from: Vec::new(),
+            }
         )
     }
 
@@ -194,9 +259,18 @@ mod test {
         };
         assert_eq!(
             result,
-            StringTemplaterError::UnknownField(
-                "The field `namey` does not exist in the hashmap.".to_string()
-            )
+            StringTemplaterError::UnknownField {
+                key: "namey".to_string(),
+                via_mirror_of: None,
+                suggestions: vec!["name".to_string()].into(),
+                span: Span {
+                    start: 6,
+                    end: 15,
+                    line: 1,
+                    column: 7,
+                },
+                expanded_from: Vec::new(),
+            }
         )
     }
 
@@ -245,14 +319,23 @@ mod test {
         };
         let template_str = "Hello {{name}}! Is {{child.name}} your child? So your grandchild must be {{child.child.name}}.";
         let result = match parse(template_str, &a) {
-            Ok(_) => StringTemplaterError::MissingCurvyBracket("".to_string()),
+            Ok(_) => StringTemplaterError::SerializeError("".to_string()),
             Err(err) => err,
         };
         assert_eq!(
             result,
-            StringTemplaterError::UnknownField(
-                "The field `child.child.name` does not exist in the hashmap.".to_string()
-            )
+            StringTemplaterError::UnknownField {
+                key: "child.child.name".to_string(),
+                via_mirror_of: None,
+                suggestions: vec!["child.child".to_string()].into(),
+                span: Span {
+                    start: 73,
+                    end: 93,
+                    line: 1,
+                    column: 74,
+                },
+                expanded_from: Vec::new(),
+            }
         )
     }
 
@@ -335,18 +418,28 @@ mod test {
         };
         let template_str = "Hello {{name}}! Is it true that you're {{*key_name}} years old?";
         let result = match parse(template_str, &a) {
-            Ok(_) => StringTemplaterError::MissingCurvyBracket("".to_string()),
+            Ok(_) => StringTemplaterError::SerializeError("".to_string()),
             Err(err) => err,
         };
         assert_eq!(
             result,
-            StringTemplaterError::UnknownField(
-                "The field `surname` does not exist in the hashmap.".to_string()
-            )
+            StringTemplaterError::UnknownField {
+                key: "surname".to_string(),
+                via_mirror_of: Some("key_name".to_string()),
+                suggestions: Vec::new().into(),
+                span: Span {
+                    start: 39,
+                    end: 52,
+                    line: 1,
+                    column: 40,
+                },
+                expanded_from: Vec::new(),
+            }
         )
     }
 
     #[test]
+    #[allow(deprecated)]
     fn test_serialize_and_write_nested_field_mirror_can_fail_with_options_display_missing_fields() {
         let a = M {
             name: "Doe".to_string(),
@@ -361,6 +454,7 @@ mod test {
                 safe_parse: true,
                 display_missing_keys: true,
                 override_missing_keys: None,
+                ..Default::default()
             },
         )
         .unwrap();
@@ -371,6 +465,7 @@ mod test {
     }
 
     #[test]
+    #[allow(deprecated)]
     fn test_serialize_and_write_nested_field_mirror_can_fail_with_options_display_missing_fields_override(
     ) {
         let a = M {
@@ -385,7 +480,8 @@ mod test {
             &StringTemplaterOptions {
                 safe_parse: true,
                 display_missing_keys: true,
-                override_missing_keys: Some(Box::new(move |s| format!("[key `{}` is missing]", s))),
+                override_missing_keys: Some(Arc::new(move |s| format!("[key `{}` is missing]", s))),
+                ..Default::default()
             },
         )
         .unwrap();
@@ -397,6 +493,7 @@ mod test {
     }
 
     #[test]
+    #[allow(deprecated)]
     fn test_serialize_and_write_nested_field_mirror_can_fail_with_options_hide_missing_fields() {
         let a = M {
             name: "Doe".to_string(),
@@ -411,6 +508,7 @@ mod test {
                 safe_parse: true,
                 display_missing_keys: false,
                 override_missing_keys: None,
+                ..Default::default()
             },
         )
         .unwrap();
@@ -442,4 +540,1337 @@ mod test {
         let result = parse(template_str, &a).unwrap();
         assert_eq!(result, "You should be alice.".to_string())
     }
+
+    #[test]
+    fn test_compile_template_once() {
+        let template = Template::compile("Hello {{name}}! {{{*greeting}}}").unwrap();
+        assert_eq!(
+            template.nodes(),
+            &[
+                Node::Text("Hello ".to_string()),
+                Node::Tag(Tag {
+                    key: "name".to_string(),
+                    fallbacks: Vec::new(),
+                    default: None,
+                    filters: Vec::new(),
+                    mirror_depth: 0,
+                    nested: false,
+                    unescaped: false,
+                    source: "{{name}}".to_string(),
+                    span: Span {
+                        start: 6,
+                        end: 14,
+                        line: 1,
+                        column: 7,
+                    },
+                }),
+                Node::Text("! ".to_string()),
+                Node::Tag(Tag {
+                    key: "greeting".to_string(),
+                    fallbacks: Vec::new(),
+                    default: None,
+                    filters: Vec::new(),
+                    mirror_depth: 1,
+                    nested: true,
+                    unescaped: false,
+                    source: "{{{*greeting}}}".to_string(),
+                    span: Span {
+                        start: 16,
+                        end: 31,
+                        line: 1,
+                        column: 17,
+                    },
+                }),
+            ]
+        );
+
+        let mut a: HashMap<String, String> = HashMap::new();
+        a.insert("greeting".to_string(), "polite".to_string());
+        a.insert("polite".to_string(), "Welcome {{name}}.".to_string());
+        for name in ["Doe", "Doey"] {
+            a.insert("name".to_string(), name.to_string());
+            let result = template
+                .render(&a, &StringTemplaterOptions::default())
+                .unwrap();
+            assert_eq!(result, format!("Hello {}! Welcome {}.", name, name))
+        }
+    }
+
+    #[test]
+    fn test_tb_build_template() {
+        let mut template_builder = TemplateBuilder::new();
+        template_builder.insert("name", "Doe");
+        let template = Template::compile("Hello {{name}}!").unwrap();
+        let result = template_builder.build_template(&template).unwrap();
+        assert_eq!(result, "Hello Doe!".to_string())
+    }
+
+    #[test]
+    fn test_error_spans_through_nested_templates() {
+        let mut a: HashMap<String, String> = HashMap::new();
+        a.insert("name".to_string(), "Doe".to_string());
+        a.insert("bye".to_string(), "Bye {{name}},\n{{{polite}}}".to_string());
+        a.insert("polite".to_string(), "see you {{time}}.".to_string());
+
+        let template_str = "Hello {{name}}!\n\n{{{bye}}}";
+        let result = match generate(template_str, &a) {
+            Ok(_) => StringTemplaterError::SerializeError("".to_string()),
+            Err(err) => err,
+        };
+        assert_eq!(
+            result,
+            StringTemplaterError::UnknownField {
+                key: "time".to_string(),
+                via_mirror_of: None,
+                suggestions: Vec::new().into(),
+                span: Span {
+                    start: 8,
+                    end: 16,
+                    line: 1,
+                    column: 9,
+                },
+                expanded_from: vec![
+                    Span {
+                        start: 17,
+                        end: 26,
+                        line: 3,
+                        column: 1,
+                    },
+                    Span {
+                        start: 14,
+                        end: 26,
+                        line: 2,
+                        column: 1,
+                    },
+                ],
+            }
+        )
+    }
+
+    #[test]
+    fn test_error_display() {
+        let mut a: HashMap<String, String> = HashMap::new();
+        a.insert("name".to_string(), "Doe".to_string());
+
+        let result = generate("Hello {{{name}}!", &a).unwrap_err();
+        assert_eq!(
+            result.to_string(),
+            "Missing curvy bracket: `Missing one curvy bracket `}` around `name`.`".to_string()
+        );
+        let result = generate("Hello {{surname}}!", &a).unwrap_err();
+        assert_eq!(
+            result.to_string(),
+            "Unknown field: `The field `surname` does not exist in the hashmap.`".to_string()
+        )
+    }
+
+    #[test]
+    fn test_diagnose_collects_all_errors() {
+        let mut a: HashMap<String, String> = HashMap::new();
+        a.insert("name".to_string(), "Doe".to_string());
+        a.insert("bye".to_string(), "Bye {{surname}}.".to_string());
+
+        let template_str = "Hello {{namey}}! {{{bye}}} Are you {{age}} or {{name}";
+        let (result, errors) =
+            Template::diagnose(template_str, &a, &StringTemplaterOptions::default());
+        assert_eq!(result, "Hello ! Bye . Are you  or {{name}".to_string());
+        assert_eq!(
+            errors
+                .iter()
+                .map(|err| err.to_string())
+                .collect::<Vec<String>>(),
+            vec![
+                "Unknown field: `The field `namey` does not exist in the hashmap.`".to_string(),
+                "Unknown field: `The field `surname` does not exist in the hashmap.`".to_string(),
+                "Unknown field: `The field `age` does not exist in the hashmap.`".to_string(),
+                "Missing curvy bracket: `Missing one curvy bracket `}` around `name`.`".to_string(),
+            ]
+        )
+    }
+
+    #[test]
+    fn test_referenced_keys() {
+        let template = Template::compile("{{name}} is {{**age_key}}: {{{*bye}}}").unwrap();
+        let keys = template.referenced_keys();
+        assert_eq!(
+            keys.iter()
+                .map(|reference| (reference.key.as_str(), reference.kind))
+                .collect::<Vec<(&str, KeyKind)>>(),
+            vec![
+                ("name", KeyKind::Plain),
+                ("age_key", KeyKind::Mirror { depth: 2 }),
+                ("bye", KeyKind::Nested { mirror_depth: 1 }),
+            ]
+        );
+        assert_eq!(
+            keys.iter()
+                .map(|reference| reference.reaches_dynamic_keys())
+                .collect::<Vec<bool>>(),
+            vec![false, true, true]
+        );
+        assert_eq!(
+            keys[1].span,
+            Span {
+                start: 12,
+                end: 25,
+                line: 1,
+                column: 13,
+            }
+        )
+    }
+
+    #[test]
+    fn test_validate_against_sample() {
+        let a = Person {
+            name: "Roger".to_string(),
+            child: Some(Box::new(Person {
+                name: "Betty".to_string(),
+                child: None,
+            })),
+        };
+        assert_eq!(
+            validate("{{name}} {{child.name}} {{child.child.name}}", &a),
+            Ok(())
+        );
+
+        let errors = validate("{{name}} {{child.nmae}} {{*name}}", &a).unwrap_err();
+        assert_eq!(
+            errors,
+            vec![StringTemplaterError::UnknownField {
+                key: "child.nmae".to_string(),
+                via_mirror_of: None,
+                suggestions: vec!["child.name".to_string()].into(),
+                span: Span {
+                    start: 9,
+                    end: 23,
+                    line: 1,
+                    column: 10,
+                },
+                expanded_from: Vec::new(),
+            }]
+        )
+    }
+
+    #[test]
+    fn test_validate_array_indices() {
+        let a = V {
+            items: vec![W {
+                name: "john".to_string(),
+            }],
+        };
+        assert_eq!(validate("{{items.3.name}}", &a), Ok(()));
+        assert_eq!(validate("{{items.3.age}}", &a).unwrap_err().len(), 1);
+        assert_eq!(
+            validate_hashmap("{{items.3.name}}", &parse_to_hashmap(&a).unwrap()),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_validate_fallbacks() {
+        let sample = serde_json::json!({ "first_name": "John" });
+        assert_eq!(validate("{{nickname ?? \"Anonymous\"}}", &sample), Ok(()));
+        assert_eq!(validate("{{nickname ?? first_name}}", &sample), Ok(()));
+        assert_eq!(validate("{{nickname ?? surname ?? \"\"}}", &sample), Ok(()));
+        let errors = validate("{{nickname ?? surname}} {{first_name}}", &sample).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            &errors[0],
+            StringTemplaterError::UnknownField { key, .. } if key == "nickname"
+        ));
+        assert_eq!(
+            Template::compile("{{nickname ?? first_name}}")
+                .unwrap()
+                .referenced_keys()
+                .iter()
+                .map(|reference| (reference.key.as_str(), reference.fallback))
+                .collect::<Vec<_>>(),
+            vec![("nickname", false), ("first_name", true)]
+        );
+    }
+
+    #[test]
+    fn test_unknown_field_suggestions() {
+        let a = A {
+            name: "John".to_string(),
+            son: B {
+                name: "Doe".to_string(),
+                age: 20,
+            },
+        };
+        let result = match parse("Hello {{son.nmae}}!", &a) {
+            Ok(_) => StringTemplaterError::SerializeError("".to_string()),
+            Err(err) => err,
+        };
+        match result {
+            StringTemplaterError::UnknownField { suggestions, .. } => {
+                assert_eq!(suggestions, vec!["son.name".to_string()].into_boxed_slice())
+            }
+            err => panic!("Unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn test_render_to_writers() {
+        let mut a: HashMap<String, String> = HashMap::new();
+        a.insert("name".to_string(), "Doe".to_string());
+        let template = Template::compile("Hello {{name}}!").unwrap();
+
+        let mut fmt_output = String::from("> ");
+        template
+            .render_to_fmt(&a, &StringTemplaterOptions::default(), &mut fmt_output)
+            .unwrap();
+        assert_eq!(fmt_output, "> Hello Doe!".to_string());
+
+        let mut io_output: Vec<u8> = Vec::new();
+        generate_to_io(
+            "Bye {{name}}.",
+            &a,
+            &StringTemplaterOptions::default(),
+            &mut io_output,
+        )
+        .unwrap();
+        assert_eq!(io_output, b"Bye Doe.".to_vec());
+
+        let mut full_output = [0u8; 8];
+        let result = template
+            .render_to_io(
+                &a,
+                &StringTemplaterOptions::default(),
+                &mut &mut full_output[..],
+            )
+            .unwrap_err();
+        assert_eq!(
+            result,
+            StringTemplaterError::WriteError {
+                kind: std::io::ErrorKind::WriteZero,
+                message: "failed to write whole buffer".to_string(),
+            }
+        );
+        assert_eq!(&full_output, b"Hello Do");
+    }
+
+    #[test]
+    fn test_tb_build_to_fmt() {
+        let mut template_builder = TemplateBuilder::new();
+        template_builder.insert("name", "Doe");
+        let mut result = String::new();
+        template_builder
+            .build_to_fmt("Hello {{name}}!", &mut result)
+            .unwrap();
+        assert_eq!(result, "Hello Doe!".to_string())
+    }
+
+    #[test]
+    fn test_render_typed_values() {
+        #[derive(Debug, Serialize)]
+        struct Typed {
+            pub name: String,
+            pub age: i32,
+            pub married: bool,
+            pub nickname: Option<String>,
+            pub scores: Vec<f32>,
+        }
+
+        let a = Typed {
+            name: "Doe".to_string(),
+            age: 35,
+            married: false,
+            nickname: None,
+            scores: vec![1.5, 2.0],
+        };
+        let value = parse_to_value(&a).unwrap();
+        assert_eq!(value["age"], Value::from(35));
+
+        let template = Template::compile(
+            "{{name}} ({{age}}, {{married}}, {{nickname}}) scored {{scores.1}} in {{scores}}.",
+        )
+        .unwrap();
+        let result = template
+            .render(&value, &StringTemplaterOptions::default())
+            .unwrap();
+        assert_eq!(
+            result,
+            "Doe (35, false, null) scored 2.0 in [1.5,2.0].".to_string()
+        )
+    }
+
+    #[test]
+    fn test_data_sources() {
+        let template_str = "Hello {{name}}! Is {{child.name}} your child?";
+        let mut a: BTreeMap<String, String> = BTreeMap::new();
+        a.insert("name".to_string(), "Roger".to_string());
+        a.insert("child.name".to_string(), "Betty".to_string());
+        let result = generate(template_str, &a).unwrap();
+        assert_eq!(result, "Hello Roger! Is Betty your child?".to_string());
+
+        let value = serde_json::json!({ "name": "Roger", "child": { "name": "Betty" } });
+        let result = generate(template_str, &value).unwrap();
+        assert_eq!(result, "Hello Roger! Is Betty your child?".to_string());
+
+        let lookup = |key: &str| match key {
+            "name" => Some("Roger".to_string()),
+            "child.name" => Some("Betty".to_string()),
+            _ => None,
+        };
+        let result = generate(template_str, &lookup).unwrap();
+        assert_eq!(result, "Hello Roger! Is Betty your child?".to_string());
+
+        let dynamic: &dyn DataSource = &value;
+        let result = generate("{{child}}", dynamic).unwrap();
+        assert_eq!(result, "{\"name\":\"Betty\"}".to_string());
+    }
+
+    #[test]
+    fn test_tb_insert_data_source() {
+        let mut template_builder = TemplateBuilder::new();
+        template_builder.insert("name", "Doe");
+        template_builder.insert_data_source(|key: &str| {
+            key.strip_prefix("upper.").map(|rest| rest.to_uppercase())
+        });
+        let result = template_builder
+            .build("Hello {{name}}, or should I say {{upper.doe}}?")
+            .unwrap();
+        assert_eq!(result, "Hello Doe, or should I say DOE?".to_string())
+    }
+
+    #[test]
+    fn test_serialize_and_resolve_paths_lazily() {
+        let mut a: HashMap<String, HashMap<String, i32>> = HashMap::new();
+        a.insert(
+            "scores.2024".to_string(),
+            HashMap::from([("alice".to_string(), 12), ("bob.jr".to_string(), 7)]),
+        );
+        let template_str = "Alice scored {{scores.2024.alice}} and Bob {{scores.2024.bob.jr}}.";
+        let result = parse(template_str, &a).unwrap();
+        assert_eq!(result, "Alice scored 12 and Bob 7.".to_string());
+
+        let a = Person {
+            name: "Roger".to_string(),
+            child: None,
+        };
+        let result = parse("{{name}}'s child is {{child}}.", &a).unwrap();
+        assert_eq!(result, "Roger's child is null.".to_string());
+    }
+
+    #[test]
+    fn test_fallback_keys_and_default() {
+        let mut a: HashMap<String, String> = HashMap::new();
+        a.insert("first_name".to_string(), "John".to_string());
+        a.insert("key_name".to_string(), "first_name".to_string());
+
+        let template_str = "Hello {{nickname ?? first_name ?? \"Anonymous\"}}!";
+        assert_eq!(
+            generate(template_str, &a).unwrap(),
+            "Hello John!".to_string()
+        );
+        a.remove("first_name");
+        assert_eq!(
+            generate(template_str, &a).unwrap(),
+            "Hello Anonymous!".to_string()
+        );
+
+        let template_str = "Hello {{*key_name ?? \"my \\\"friend\\\"\"}}!";
+        assert_eq!(
+            generate(template_str, &a).unwrap(),
+            "Hello my \"friend\"!".to_string()
+        );
+
+        let result = generate("Hello {{nickname ?? first_name}}!", &a).unwrap_err();
+        assert_eq!(
+            result,
+            StringTemplaterError::UnknownField {
+                key: "nickname".to_string(),
+                via_mirror_of: None,
+                suggestions: Vec::new().into(),
+                span: Span {
+                    start: 6,
+                    end: 32,
+                    line: 1,
+                    column: 7,
+                },
+                expanded_from: Vec::new(),
+            }
+        );
+
+        let result = generate("Hello {{nickname ?? \"Anonymous\" !}}!", &a).unwrap_err();
+        assert_eq!(
+            result.to_string(),
+            "Invalid tag: `{{nickname ?? \"Anonymous\" !}}`".to_string()
+        );
+    }
+
+    #[test]
+    fn test_filters() {
+        let mut a: HashMap<String, String> = HashMap::new();
+        a.insert("name".to_string(), "  jOHN doe ".to_string());
+        a.insert("empty".to_string(), "".to_string());
+        a.insert("greeting".to_string(), "hello {{name | trim}}".to_string());
+
+        let template_str = "{{name | trim | capitalize}}, {{name | upper | trim | truncate(4)}}, {{name|len}}, {{name | trim | replace(\"o\", \"0\") | lower}}, {{empty | default(\"none\")}}, {{nickname ?? \"anon\" | upper}}, {{{greeting | upper}}}";
+        let result = generate(template_str, &a).unwrap();
+        assert_eq!(
+            result,
+            "John doe, JOHN, 11, john d0e, none, ANON, HELLO JOHN DOE".to_string()
+        );
+
+        let template = Template::compile("{{name | reverse}}").unwrap();
+        assert_eq!(
+            template.nodes(),
+            &[Node::Tag(Tag {
+                key: "name".to_string(),
+                fallbacks: Vec::new(),
+                default: None,
+                filters: vec![FilterCall {
+                    name: "reverse".to_string(),
+                    args: Vec::new(),
+                }],
+                mirror_depth: 0,
+                nested: false,
+                unescaped: false,
+                source: "{{name | reverse}}".to_string(),
+                span: Span {
+                    start: 0,
+                    end: 18,
+                    line: 1,
+                    column: 1,
+                },
+            })]
+        );
+        assert_eq!(
+            template
+                .render(&a, &StringTemplaterOptions::default())
+                .unwrap_err()
+                .to_string(),
+            "Unknown filter: `reverse`".to_string()
+        );
+        assert_eq!(
+            generate("{{name | truncate(\"4\")}}", &a)
+                .unwrap_err()
+                .to_string(),
+            "Invalid filter arguments: `truncate`".to_string()
+        );
+        assert_eq!(
+            generate("{{name | truncate(4}}", &a)
+                .unwrap_err()
+                .to_string(),
+            "Invalid tag: `{{name | truncate(4}}`".to_string()
+        );
+    }
+
+    #[test]
+    fn test_custom_filters() {
+        let mut a: HashMap<String, String> = HashMap::new();
+        a.insert("price".to_string(), "1250".to_string());
+        a.insert("sku".to_string(), "ab12".to_string());
+
+        let mut options = StringTemplaterOptions::default();
+        options
+            .filters
+            .register("cents", |value: &str, args: &[Arg]| {
+                let cents = value.parse::<u64>().map_err(|err| err.to_string())?;
+                let currency = args.first().and_then(Arg::as_str).unwrap_or("$");
+                Ok::<String, String>(format!("{}{}.{:02}", currency, cents / 100, cents % 100))
+            });
+        let result = generate_with_options("{{price | cents(\"€\")}}", &a, &options).unwrap();
+        assert_eq!(result, "€12.50".to_string());
+
+        let result = generate_with_options("{{sku | cents}}", &a, &options).unwrap_err();
+        assert_eq!(
+            result,
+            StringTemplaterError::FilterError {
+                name: "cents".to_string(),
+                message: "invalid digit found in string".to_string(),
+                span: Span {
+                    start: 0,
+                    end: 15,
+                    line: 1,
+                    column: 1,
+                },
+                expanded_from: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_tb_register_filter() {
+        let mut template_builder = TemplateBuilder::new();
+        template_builder.insert("sku", "ab12");
+        template_builder.register_filter("sku", |value: &str, _: &[Arg]| {
+            Ok::<String, std::fmt::Error>(format!("SKU-{}", value.to_uppercase()))
+        });
+        let result = template_builder.build("Item {{sku | sku}}").unwrap();
+        assert_eq!(result, "Item SKU-AB12".to_string())
+    }
+
+    #[test]
+    fn test_conditional_blocks() {
+        let mut a: HashMap<String, String> = HashMap::new();
+        a.insert("first_name".to_string(), "John".to_string());
+        a.insert("middle_name".to_string(), "".to_string());
+        a.insert("last_name".to_string(), "Doe".to_string());
+        a.insert("admin".to_string(), "false".to_string());
+        a.insert("member".to_string(), "true".to_string());
+
+        let template_str = "{{first_name}} {{#if middle_name}}{{middle_name}} {{/if}}{{last_name}} is {{#if admin}}an admin{{else if member}}a {{#if premium}}premium {{/if}}member{{else}}a guest{{/if}}.";
+        assert_eq!(
+            generate(template_str, &a).unwrap(),
+            "John Doe is a member.".to_string()
+        );
+        a.insert("middle_name".to_string(), "Jack".to_string());
+        a.insert("member".to_string(), "0".to_string());
+        assert_eq!(
+            generate(template_str, &a).unwrap(),
+            "John Jack Doe is a guest.".to_string()
+        );
+
+        let value = serde_json::json!({ "name": "Roger", "child": null });
+        assert_eq!(
+            generate("{{#if child}}has a child{{else}}{{name}}{{/if}}", &value).unwrap(),
+            "Roger".to_string()
+        );
+        assert_eq!(
+            Template::compile("{{#if child}}yes{{else}}no{{/if}}")
+                .unwrap()
+                .nodes(),
+            &[Node::If(IfBlock {
+                branches: vec![IfBranch {
+                    key: "child".to_string(),
+                    nodes: vec![Node::Text("yes".to_string())],
+                    span: Span {
+                        start: 0,
+                        end: 13,
+                        line: 1,
+                        column: 1,
+                    },
+                }],
+                otherwise: vec![Node::Text("no".to_string())],
+                span: Span {
+                    start: 0,
+                    end: 33,
+                    line: 1,
+                    column: 1,
+                },
+            })]
+        );
+    }
+
+    #[test]
+    fn test_blocks_on_typed_values() {
+        let value = serde_json::json!({
+            "empty": [],
+            "zero": 0.0,
+            "obj": {},
+            "text": "false",
+            "word": "yes",
+            "items": [0, 1, "", [], { "a": 1 }],
+        });
+        assert_eq!(
+            generate(
+                "{{#if empty}}E{{/if}}{{#if zero}}Z{{/if}}{{#if obj}}O{{/if}}{{#if text}}T{{/if}}{{#if word}}W{{/if}}",
+                &value
+            )
+            .unwrap(),
+            "W".to_string()
+        );
+        assert_eq!(
+            generate(
+                "{{#each items as item}}{{#if item}}y{{else}}n{{/if}}{{/each}}{{#each empty as item}}{{item}}{{else}}!{{/each}}",
+                &value
+            )
+            .unwrap(),
+            "nynny!".to_string()
+        );
+
+        let mut template_builder = TemplateBuilder::new();
+        template_builder.insert("zero", "0.0");
+        template_builder.insert_data_source(value);
+        assert_eq!(
+            template_builder
+                .build("{{#if zero}}Z{{/if}}{{#if text}}T{{/if}}{{#each items as item}}{{@index}}{{/each}}")
+                .unwrap(),
+            "01234".to_string()
+        );
+
+        #[derive(Serialize)]
+        struct Flags {
+            admin: String,
+            member: String,
+        }
+        let flags = Flags {
+            admin: "0".to_string(),
+            member: "false".to_string(),
+        };
+        let template_str = "{{#if admin}}A{{/if}}{{#if member}}M{{/if}}";
+        assert_eq!(parse(template_str, &flags).unwrap(), "".to_string());
+        assert_eq!(
+            generate(template_str, &parse_to_hashmap(&flags).unwrap()).unwrap(),
+            "".to_string()
+        );
+    }
+
+    #[test]
+    fn test_unclosed_blocks() {
+        let a: HashMap<String, String> = HashMap::new();
+
+        let result = generate("Hello\n{{#if name}}{{name}}{{#if age}}{{/if}}", &a).unwrap_err();
+        assert_eq!(
+            result,
+            StringTemplaterError::UnclosedBlock {
+                block: "if".to_string(),
+                span: Span {
+                    start: 6,
+                    end: 18,
+                    line: 2,
+                    column: 1,
+                },
+                expanded_from: Vec::new(),
+            }
+        );
+        assert_eq!(
+            result.to_string(),
+            "Unclosed block: `The block `{{#if}}` is never closed by `{{/if}}`.`".to_string()
+        );
+        assert_eq!(
+            generate("{{#if name}}{{else}}{{else}}{{/if}}", &a)
+                .unwrap_err()
+                .to_string(),
+            "Invalid tag: `{{else}}`".to_string()
+        );
+        assert_eq!(
+            generate("Hello{{/if}}", &a).unwrap_err().to_string(),
+            "Invalid tag: `{{/if}}`".to_string()
+        );
+        assert_eq!(
+            generate("{{#if}}{{/if}}", &a).unwrap_err().to_string(),
+            "Invalid tag: `{{#if}}`".to_string()
+        );
+    }
+
+    #[test]
+    fn test_block_depth_limit() {
+        let mut a: HashMap<String, String> = HashMap::new();
+        a.insert("x".to_string(), "1".to_string());
+        let nested = |depth: usize, content: &str| {
+            format!(
+                "{}{}{}",
+                "{{#if x}}".repeat(depth),
+                content,
+                "{{/if}}".repeat(depth)
+            )
+        };
+
+        let exceeded = StringTemplaterError::LimitExceeded {
+            limit: Limit::BlockDepth(64),
+        };
+        assert_eq!(generate(&nested(64, "x"), &a).unwrap(), "x".to_string());
+        assert_eq!(generate(&nested(5000, "x"), &a).unwrap_err(), exceeded);
+        assert_eq!(
+            Template::diagnose(&nested(5000, "x"), &a, &StringTemplaterOptions::default()).1,
+            vec![exceeded.clone()]
+        );
+
+        a.insert("inner".to_string(), nested(1, "x"));
+        assert_eq!(
+            generate(&nested(63, "{{{inner}}}"), &a).unwrap(),
+            "x".to_string()
+        );
+        a.insert("inner".to_string(), nested(2, "x"));
+        assert_eq!(
+            generate(&nested(63, "{{{inner}}}"), &a).unwrap_err(),
+            exceeded
+        );
+    }
+
+    #[test]
+    fn test_loop_blocks() {
+        #[derive(Debug, Serialize)]
+        struct Item {
+            name: String,
+            tags: Vec<String>,
+        }
+        #[derive(Debug, Serialize)]
+        struct Cart {
+            items: Vec<Item>,
+            coupons: Vec<String>,
+        }
+        let cart = Cart {
+            items: vec![
+                Item {
+                    name: "Apple".to_string(),
+                    tags: vec!["fruit".to_string(), "red".to_string()],
+                },
+                Item {
+                    name: "Bread".to_string(),
+                    tags: Vec::new(),
+                },
+            ],
+            coupons: Vec::new(),
+        };
+        let template_str = "{{#each items as item}}{{@index}}. {{item.name}} ({{#each item.tags as tag}}{{tag}}{{#if @last}}{{else}}, {{/if}}{{else}}no tags{{/each}}){{#if @last}}.{{else}}; {{/if}}{{/each}}{{#each coupons as coupon}}{{coupon}}{{else}} No coupon.{{/each}}";
+        let expected = "0. Apple (fruit, red); 1. Bread (no tags). No coupon.".to_string();
+        assert_eq!(
+            generate(template_str, &parse_to_hashmap(&cart).unwrap()).unwrap(),
+            expected
+        );
+        assert_eq!(
+            generate(template_str, &parse_to_value(&cart).unwrap()).unwrap(),
+            expected
+        );
+        assert_eq!(parse(template_str, &cart).unwrap(), expected);
+
+        let result = generate(
+            "{{#each items as item}}{{item.price}}{{/each}}",
+            &parse_to_hashmap(&cart).unwrap(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            result.to_string(),
+            "Unknown field: `The field `items.0.price` does not exist in the hashmap.`".to_string()
+        );
+        assert_eq!(
+            Template::compile("{{#each items as item}}{{item.name}} {{@first}}{{/each}}")
+                .unwrap()
+                .referenced_keys()
+                .iter()
+                .map(|reference| (reference.key.as_str(), reference.kind))
+                .collect::<Vec<_>>(),
+            vec![("items", KeyKind::Loop), ("items.0.name", KeyKind::Plain)]
+        );
+        assert_eq!(
+            Template::compile(
+                "{{#each items as items}}{{#each items.tags as t}}{{t}}{{/each}}{{/each}}"
+            )
+            .unwrap()
+            .referenced_keys()
+            .iter()
+            .map(|reference| reference.key.as_str())
+            .collect::<Vec<_>>(),
+            vec!["items", "items.0.tags", "items.0.tags.0"]
+        );
+        assert_eq!(
+            parse("{{#each items}}{{/each}}", &cart)
+                .unwrap_err()
+                .to_string(),
+            "Invalid tag: `{{#each items}}`".to_string()
+        );
+
+        struct CountedKeys {
+            datas: HashMap<String, String>,
+            calls: AtomicUsize,
+        }
+        impl DataSource for CountedKeys {
+            fn get(&self, key: &str) -> Option<Cow<'_, str>> {
+                self.datas
+                    .get(key)
+                    .map(|value| Cow::Borrowed(value.as_str()))
+            }
+            fn keys(&self) -> Vec<String> {
+                self.calls.fetch_add(1, Ordering::SeqCst);
+                self.datas.keys().cloned().collect()
+            }
+        }
+        let counted = CountedKeys {
+            datas: parse_to_hashmap(&cart).unwrap(),
+            calls: AtomicUsize::new(0),
+        };
+        assert_eq!(generate(template_str, &counted).unwrap(), expected);
+        assert_eq!(counted.calls.load(Ordering::SeqCst), 1);
+
+        let mut template_builder = TemplateBuilder::new();
+        template_builder.insert("items.0.name", "Tea");
+        template_builder.insert_data_source(parse_to_hashmap(&cart).unwrap());
+        assert_eq!(
+            template_builder
+                .build("{{#each items as item}}{{item.name}}{{/each}}")
+                .unwrap(),
+            "Tea".to_string()
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        let mut a: HashMap<String, String> = HashMap::new();
+        a.insert("name".to_string(), "Doe".to_string());
+
+        let template_str =
+            "Hello {{! the name of the user }}{{name}}{{!--\n  A comment with `}}`\n--}}!";
+        assert_eq!(
+            generate(template_str, &a).unwrap(),
+            "Hello Doe!".to_string()
+        );
+        assert_eq!(
+            Template::compile(template_str).unwrap().nodes(),
+            &[
+                Node::Text("Hello ".to_string()),
+                Node::Tag(Tag {
+                    key: "name".to_string(),
+                    fallbacks: Vec::new(),
+                    default: None,
+                    filters: Vec::new(),
+                    mirror_depth: 0,
+                    nested: false,
+                    unescaped: false,
+                    source: "{{name}}".to_string(),
+                    span: Span {
+                        start: 33,
+                        end: 41,
+                        line: 1,
+                        column: 34,
+                    },
+                }),
+                Node::Text("!".to_string()),
+            ]
+        );
+
+        let result = generate("Hello {{!-- unclosed }}", &a).unwrap_err();
+        assert_eq!(
+            result,
+            StringTemplaterError::MissingCurvyBracket {
+                expected: 2,
+                found: 0,
+                key: "unclosed }}".to_string(),
+                span: Span {
+                    start: 6,
+                    end: 23,
+                    line: 1,
+                    column: 7,
+                },
+                expanded_from: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_whitespace_control() {
+        let mut a: HashMap<String, String> = HashMap::new();
+        a.insert("name".to_string(), "Doe".to_string());
+        a.insert("key_name".to_string(), "name".to_string());
+        a.insert("greeting".to_string(), "  Hello  {{- name}}".to_string());
+        a.insert("-5".to_string(), "minus five".to_string());
+
+        let template_str = "Hello\n  {{- name -}}  \n!\n{{{- greeting -}}}\n{{*key_name -}} ,\n{{-! comment -}}\n{{\\-5}}";
+        assert_eq!(
+            generate(template_str, &a).unwrap(),
+            "HelloDoe!  HelloDoeDoe,minus five".to_string()
+        );
+
+        let value = serde_json::json!({ "items": ["a", "b"] });
+        let template_str = "<ul>\n  {{- #each items as item }}\n  <li>{{item}}</li>\n  {{- /each }}\n</ul>{{#if items -}}  yes  {{- else -}}  no  {{- /if}}";
+        assert_eq!(
+            generate(template_str, &value).unwrap(),
+            "<ul>\n  <li>a</li>\n  <li>b</li>\n</ul>yes".to_string()
+        );
+    }
+
+    #[test]
+    fn test_custom_delimiters() {
+        let mut a: HashMap<String, String> = HashMap::new();
+        a.insert("name".to_string(), "Doe".to_string());
+        a.insert("key_name".to_string(), "name".to_string());
+        a.insert("greeting".to_string(), "Hello <%name%>".to_string());
+
+        let options = StringTemplaterOptions {
+            delimiters: Delimiters::new("<%", "%>").unwrap(),
+            ..Default::default()
+        };
+        let template_str =
+            "{{name}}: <%%greeting%%>, <%*key_name%> \\<% 100\\% <%#if name -%> {{#if}} <%- /if%>";
+        assert_eq!(
+            generate_with_options(template_str, &a, &options).unwrap(),
+            "{{name}}: Hello Doe, Doe <% 100\\% {{#if}}".to_string()
+        );
+        assert_eq!(
+            generate_with_options("\\\\<%name%> <\\%name%\\> \\\\%", &a, &options).unwrap(),
+            "\\Doe <%name%> \\\\%".to_string()
+        );
+        assert_eq!(
+            generate_with_options("\\{x\\} \\\\ 50\\% <\\%name%>", &a, &options).unwrap(),
+            "\\{x\\} \\\\ 50\\% <%name%>".to_string()
+        );
+        assert_eq!(
+            generate_with_options("<%name %}", &a, &options).unwrap_err(),
+            StringTemplaterError::MissingCurvyBracket {
+                expected: 2,
+                found: 1,
+                key: "name ".to_string(),
+                span: Span {
+                    start: 0,
+                    end: 8,
+                    line: 1,
+                    column: 1,
+                },
+                expanded_from: Vec::new(),
+            }
+        );
+
+        let mut template_builder = TemplateBuilder::new();
+        template_builder.insert("name", "Doe");
+        template_builder.set_delimiters("${", "}").unwrap();
+        assert_eq!(
+            template_builder
+                .build("\\documentclass{article} ${name} ${{*missing ?? \"\"}}")
+                .unwrap(),
+            "\\documentclass{article} Doe ".to_string()
+        );
+        assert_eq!(
+            template_builder.build("\\\\{article} $\\{name}").unwrap(),
+            "\\\\{article} ${name}".to_string()
+        );
+        assert_eq!(
+            template_builder.set_delimiters("", "}").unwrap_err(),
+            StringTemplaterError::InvalidDelimiters {
+                open: "".to_string(),
+                close: "}".to_string(),
+            }
+        );
+        assert!(Delimiters::new("|", "|").is_err());
+        assert!(Delimiters::new("[[", "-]]").is_err());
+        assert!(Delimiters::new("[", ".").is_err());
+        assert!(Delimiters::new("a", "b").is_err());
+        assert!(Delimiters::new("@(", ")").is_err());
+        assert!(Delimiters::new("[_", ",]").is_err());
+
+        let delimiters = Delimiters::new("<%", "%>").unwrap();
+        let errors = validate_with_delimiters(
+            "<%nmae%> {{nmae}}",
+            &serde_json::json!({ "name": 1 }),
+            &delimiters,
+        )
+        .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span().unwrap().start, 0);
+        assert!(validate_hashmap_with_delimiters("<%name%> {{nmae}}", &a, &delimiters).is_ok());
+    }
+
+    #[test]
+    fn test_raw_blocks() {
+        let mut a: HashMap<String, String> = HashMap::new();
+        a.insert("name".to_string(), "Doe".to_string());
+        a.insert("raw".to_string(), "not raw".to_string());
+
+        let template_str = "Write {{raw}}{{name}} or \\{{{*name}} {{! kept }}{{/raw}} to get {{name}}.\n{{- raw -}}\n  {{#if}}\n{{- /raw}}";
+        assert_eq!(
+            generate(template_str, &a).unwrap(),
+            "Write {{name}} or \\{{{*name}} {{! kept }} to get Doe.{{#if}}".to_string()
+        );
+        assert_eq!(
+            Template::compile("{{raw}}{{/if}}{{/raw}}").unwrap().nodes(),
+            &[Node::Text("{{/if}}".to_string())]
+        );
+        assert_eq!(
+            generate("{{raw}} is {{name}}", &a).unwrap(),
+            "not raw is Doe".to_string()
+        );
+    }
+
+    #[test]
+    fn test_escaping() {
+        let mut a: HashMap<String, String> = HashMap::new();
+        a.insert("name".to_string(), "<b>Tom & \"Jerry\"</b>".to_string());
+        a.insert("bio".to_string(), "<i>cat's friend</i>".to_string());
+        a.insert("card".to_string(), "<p>{{name}}</p>".to_string());
+
+        let mut options = StringTemplaterOptions {
+            escaping: Escaping::Html,
+            ..Default::default()
+        };
+        assert_eq!(
+            generate_with_options("{{name}} {{&bio}} {{{card}}} {{{&card}}}", &a, &options)
+                .unwrap(),
+            "&lt;b&gt;Tom &amp; &quot;Jerry&quot;&lt;/b&gt; <i>cat's friend</i> <p>&lt;b&gt;Tom &amp; &quot;Jerry&quot;&lt;/b&gt;</p> <p><b>Tom & \"Jerry\"</b></p>".to_string()
+        );
+        assert_eq!(
+            generate_with_options(
+                "{{{card | trim}}} {{{card | replace(\"p>\", \"i>\")}}} {{{&card | replace(\"p>\", \"i>\")}}}",
+                &a,
+                &options
+            )
+            .unwrap(),
+            "<p>&lt;b&gt;Tom &amp; &quot;Jerry&quot;&lt;/b&gt;</p> <i>&lt;b&gt;Tom &amp; &quot;Jerry&quot;&lt;/b&gt;</i> <i><b>Tom & \"Jerry\"</b></i>".to_string()
+        );
+        options.escaping = Escaping::JsonString;
+        assert_eq!(
+            generate_with_options("{\"name\": \"{{name}}\"}", &a, &options).unwrap(),
+            "{\"name\": \"<b>Tom & \\\"Jerry\\\"</b>\"}".to_string()
+        );
+        options.escaping = Escaping::UrlComponent;
+        assert_eq!(
+            generate_with_options("https://example.com/?q={{bio}}", &a, &options).unwrap(),
+            "https://example.com/?q=%3Ci%3Ecat%27s%20friend%3C%2Fi%3E".to_string()
+        );
+
+        let mut template_builder = TemplateBuilder::new();
+        template_builder.insert("file", "it's mine.txt");
+        assert_eq!(template_builder.escaping(), Escaping::None);
+        template_builder.set_escaping(Escaping::PosixShell);
+        assert_eq!(template_builder.escaping(), Escaping::PosixShell);
+        assert_eq!(
+            template_builder.build("cat {{file}}").unwrap(),
+            "cat 'it'\\''s mine.txt'".to_string()
+        );
+    }
+
+    #[test]
+    fn test_recursion_limit() {
+        let mut a: HashMap<String, String> = HashMap::new();
+        a.insert("a".to_string(), "{{{b}}}".to_string());
+        a.insert("b".to_string(), "x{{{a}}}".to_string());
+        a.insert("self".to_string(), "{{{self}}}".to_string());
+        a.insert("k1".to_string(), "{{{k2}}}".to_string());
+        a.insert("k2".to_string(), "{{{k3}}}".to_string());
+        a.insert("k3".to_string(), "end".to_string());
+
+        let result = generate("{{{a}}}", &a).unwrap_err();
+        assert_eq!(
+            result,
+            StringTemplaterError::RecursionLimit {
+                chain: vec!["a".to_string(), "b".to_string(), "a".to_string()],
+                span: Span {
+                    start: 1,
+                    end: 8,
+                    line: 1,
+                    column: 2,
+                },
+                expanded_from: vec![
+                    Span {
+                        start: 0,
+                        end: 7,
+                        line: 1,
+                        column: 1,
+                    },
+                    Span {
+                        start: 0,
+                        end: 7,
+                        line: 1,
+                        column: 1,
+                    },
+                ],
+            }
+        );
+        assert_eq!(result.to_string(), "Recursion limit: `a -> b -> a`");
+
+        let (result, errors) =
+            Template::diagnose("Hello {{{self}}}!", &a, &StringTemplaterOptions::default());
+        assert_eq!(result, "Hello !".to_string());
+        assert_eq!(errors[0].to_string(), "Recursion limit: `self -> self`");
+
+        let mut options = StringTemplaterOptions::default();
+        assert_eq!(
+            generate_with_options("{{{k1}}}", &a, &options).unwrap(),
+            "end".to_string()
+        );
+        options.max_nesting_depth = 2;
+        assert_eq!(
+            generate_with_options("{{{k1}}}", &a, &options)
+                .unwrap_err()
+                .to_string(),
+            "Recursion limit: `k1 -> k2 -> k3`"
+        );
+    }
+
+    #[test]
+    fn test_render_limits() {
+        let mut a: HashMap<String, String> = HashMap::new();
+        a.insert("a".to_string(), "{{{b}}}{{{b}}}".to_string());
+        a.insert("b".to_string(), "{{{c}}}{{{c}}}".to_string());
+        a.insert("c".to_string(), "0123456789".to_string());
+        a.insert("key_name".to_string(), "c".to_string());
+
+        let mut options = StringTemplaterOptions {
+            max_output_bytes: Some(50),
+            max_substitutions: Some(8),
+            max_mirror_depth: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            generate_with_options("{{{a}}}{{*key_name}}", &a, &options)
+                .unwrap()
+                .len(),
+            50
+        );
+        assert_eq!(
+            generate_with_options("{{{a}}}{{**key_name}}", &a, &options).unwrap_err(),
+            StringTemplaterError::LimitExceeded {
+                limit: Limit::MirrorDepth(1),
+            }
+        );
+        assert_eq!(
+            generate_with_options("{{{a}}}{{c}}!", &a, &options)
+                .unwrap_err()
+                .to_string(),
+            "Limit exceeded: `The template goes more than 50 output bytes.`".to_string()
+        );
+        options.max_output_bytes = None;
+        assert_eq!(
+            generate_with_options("{{{a}}}{{c}}{{c}}", &a, &options).unwrap_err(),
+            StringTemplaterError::LimitExceeded {
+                limit: Limit::Substitutions(8),
+            }
+        );
+
+        let (result, errors) = Template::diagnose("{{{a}}} {{{a}}}", &a, &options);
+        assert_eq!(result.len(), 41);
+        assert_eq!(
+            errors,
+            vec![StringTemplaterError::LimitExceeded {
+                limit: Limit::Substitutions(8),
+            }]
+        );
+
+        let options = StringTemplaterOptions {
+            max_output_bytes: Some(100),
+            max_substitutions: Some(10),
+            max_block_depth: 2,
+            ..Default::default()
+        };
+        let filter = "|replace(\"0\", \"0000000000\")";
+        assert_eq!(
+            generate_with_options(&format!("{{{{c{}}}}}", filter), &a, &options)
+                .unwrap()
+                .len(),
+            19
+        );
+        assert_eq!(
+            generate_with_options(&format!("{{{{c{}}}}}", filter.repeat(7)), &a, &options)
+                .unwrap_err(),
+            StringTemplaterError::LimitExceeded {
+                limit: Limit::OutputBytes(100),
+            }
+        );
+        assert_eq!(
+            generate_with_options("{{#if c}}{{#if c}}{{c}}{{/if}}{{/if}}", &a, &options).unwrap(),
+            "0123456789".to_string()
+        );
+        assert_eq!(
+            generate_with_options(
+                "{{#if c}}{{#if c}}{{#if c}}{{/if}}{{/if}}{{/if}}",
+                &a,
+                &options
+            )
+            .unwrap_err(),
+            StringTemplaterError::LimitExceeded {
+                limit: Limit::BlockDepth(2),
+            }
+        );
+
+        let items = serde_json::json!({ "items": [0, 1, 2, 3, 4, 5, 6, 7, 8, 9] });
+        let options = StringTemplaterOptions {
+            max_substitutions: Some(1000),
+            ..Default::default()
+        };
+        let template_str = "{{#each items as a}}{{#each items as b}}{{#each items as c}}{{#if items}}{{/if}}{{/each}}{{/each}}{{/each}}";
+        assert_eq!(
+            generate_with_options(template_str, &items, &options).unwrap_err(),
+            StringTemplaterError::LimitExceeded {
+                limit: Limit::Substitutions(1000),
+            }
+        );
+        assert_eq!(
+            generate_with_options(
+                "{{#each items as a}}{{#if a}}{{/if}}{{/each}}",
+                &items,
+                &options
+            )
+            .unwrap(),
+            String::new()
+        );
+    }
+
+    #[test]
+    fn test_shared_builder() {
+        fn assert_shareable<T: Send + Sync + Clone + std::fmt::Debug>() {}
+        assert_shareable::<StringTemplaterOptions>();
+        assert_shareable::<TemplateBuilder>();
+
+        static BUILDER: OnceLock<TemplateBuilder> = OnceLock::new();
+        let builder = BUILDER.get_or_init(|| {
+            let mut template_builder = TemplateBuilder::new();
+            template_builder.insert("name", "Doe");
+            template_builder.set_safe_parse(true);
+            template_builder.display_missing_keys();
+            template_builder.override_missing_keys_message(Arc::new(|s| format!("<{}>", s)));
+            template_builder
+                .register_filter("shout", |value, _| Ok::<_, String>(format!("{}!", value)));
+            template_builder
+        });
+        let mut forked = builder.clone();
+        forked.insert("name", "Alice");
+
+        let handle = thread::spawn(move || forked.build("{{name|shout}} {{age}}").unwrap());
+        assert_eq!(handle.join().unwrap(), "Alice! <age>".to_string());
+        assert_eq!(
+            builder.build("{{name|shout}} {{age}}").unwrap(),
+            "Doe! <age>".to_string()
+        );
+        assert!(format!("{:?}", builder).contains("custom_filters: [\"shout\"]"));
+    }
+
+    #[test]
+    fn test_missing_key_policy() {
+        let mut a: HashMap<String, String> = HashMap::new();
+        a.insert("name".to_string(), "Doe".to_string());
+        let template_str = "{{name}} {{ age | upper }} {{{*nested}}}";
+
+        let mut options = StringTemplaterOptions::default();
+        assert!(generate_with_options(template_str, &a, &options).is_err());
+        options.missing_keys = MissingKeyPolicy::Remove;
+        assert_eq!(
+            generate_with_options(template_str, &a, &options).unwrap(),
+            "Doe  ".to_string()
+        );
+        options.missing_keys = MissingKeyPolicy::KeepTag;
+        let kept = generate_with_options(template_str, &a, &options).unwrap();
+        assert_eq!(kept, "Doe {{ age | upper }} {{{*nested}}}".to_string());
+        a.insert("age".to_string(), "thirty".to_string());
+        a.insert("nested".to_string(), "greeting".to_string());
+        a.insert("greeting".to_string(), "Hi {{name}}".to_string());
+        assert_eq!(
+            generate(&kept, &a).unwrap(),
+            "Doe THIRTY Hi Doe".to_string()
+        );
+        options.missing_keys = MissingKeyPolicy::Placeholder(Arc::new(|key| format!("<{}>", key)));
+        options.escaping = Escaping::Html;
+        assert_eq!(
+            generate_with_options("{{name}} {{surname}}", &a, &options).unwrap(),
+            "Doe &lt;surname&gt;".to_string()
+        );
+
+        let mut template_builder = TemplateBuilder::new();
+        template_builder.set_safe_parse(true);
+        assert!(matches!(
+            template_builder.missing_key_policy(),
+            MissingKeyPolicy::Remove
+        ));
+        template_builder.display_missing_keys();
+        assert_eq!(
+            template_builder.build("{{surname}}").unwrap(),
+            "[MISSING_KEY: `surname`]".to_string()
+        );
+        template_builder.set_missing_key_policy(MissingKeyPolicy::KeepTag);
+        template_builder.set_safe_parse(true);
+        assert_eq!(
+            template_builder.build("{{surname}}").unwrap(),
+            "{{surname}}".to_string()
+        );
+        template_builder.set_safe_parse(false);
+        assert!(template_builder.build("{{surname}}").is_err());
+
+        let mut template_builder = TemplateBuilder::new();
+        template_builder.hide_missing_keys();
+        assert!(template_builder.build("{{surname}}").is_err());
+        template_builder.set_safe_parse(true);
+        assert_eq!(
+            template_builder.build("{{surname}}").unwrap(),
+            "".to_string()
+        );
+        template_builder.override_missing_keys_message(Arc::new(|s| format!("<{}>", s)));
+        template_builder.set_safe_parse(false);
+        assert!(template_builder.build("{{surname}}").is_err());
+        template_builder.set_safe_parse(true);
+        assert_eq!(
+            template_builder.build("{{surname}}").unwrap(),
+            "<surname>".to_string()
+        );
+    }
 }
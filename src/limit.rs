@@ -0,0 +1,25 @@
+use std::fmt;
+
+/// A limit of the options exceeded while rendering a template, along with its maximum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// The number of bytes written by the template.
+    OutputBytes(usize),
+    /// The number of tags rendered, including the ones of nested templates and of every loop item.
+    Substitutions(usize),
+    /// The number of mirror operators `*` of a tag.
+    MirrorDepth(usize),
+    /// The number of blocks opened inside each other, including the blocks around a nested template.
+    BlockDepth(usize),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::OutputBytes(max) => write!(f, "more than {} output bytes", max),
+            Limit::Substitutions(max) => write!(f, "more than {} substitutions", max),
+            Limit::MirrorDepth(max) => write!(f, "more than {} mirrors", max),
+            Limit::BlockDepth(max) => write!(f, "more than {} nested blocks", max),
+        }
+    }
+}
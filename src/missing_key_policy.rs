@@ -0,0 +1,35 @@
+use std::{fmt, sync::Arc};
+
+use crate::OverrideMessage;
+
+/// What to write in place of a tag whose key doesn't exist.
+#[derive(Clone, Default)]
+pub enum MissingKeyPolicy {
+    /// The rendering fails with a `StringTemplaterError::UnknownField` error.
+    #[default]
+    Error,
+    /// Nothing is written.
+    Remove,
+    /// The tag is written unchanged, such as `{{key}}`, so the output can be rendered again later.
+    KeepTag,
+    /// The text returned by the callback for the missing key is written.
+    Placeholder(OverrideMessage),
+}
+
+impl MissingKeyPolicy {
+    /// A placeholder displaying the missing key, such as ``[MISSING_KEY: `key`]``.
+    pub fn default_placeholder() -> Self {
+        MissingKeyPolicy::Placeholder(Arc::new(|key| format!("[MISSING_KEY: `{}`]", key)))
+    }
+}
+
+impl fmt::Debug for MissingKeyPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MissingKeyPolicy::Error => f.write_str("Error"),
+            MissingKeyPolicy::Remove => f.write_str("Remove"),
+            MissingKeyPolicy::KeepTag => f.write_str("KeepTag"),
+            MissingKeyPolicy::Placeholder(_) => f.write_str("Placeholder(..)"),
+        }
+    }
+}
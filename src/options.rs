@@ -1,8 +1,97 @@
-pub type OverrideMessage = Box<dyn Fn(&String) -> String>;
+use std::{borrow::Cow, fmt, sync::Arc};
 
-#[derive(Default)]
+use crate::{
+    template_parser::DEFAULT_MAX_BLOCK_DEPTH, Delimiters, Escaping, Filters, MissingKeyPolicy,
+};
+
+/// The text displayed in place of a missing key, given the key.
+pub type OverrideMessage = Arc<dyn Fn(&str) -> String + Send + Sync>;
+
+#[derive(Clone)]
 pub struct StringTemplaterOptions {
+    /// What to write in place of a tag whose key doesn't exist, failing by default.
+    pub missing_keys: MissingKeyPolicy,
+    /// When set, the missing keys are handled using `display_missing_keys` and `override_missing_keys` instead of `missing_keys`.
+    #[deprecated(note = "use `missing_keys` instead")]
     pub safe_parse: bool,
+    #[deprecated(note = "use `missing_keys` with `MissingKeyPolicy::Placeholder` instead")]
     pub display_missing_keys: bool,
+    #[deprecated(note = "use `missing_keys` with `MissingKeyPolicy::Placeholder` instead")]
     pub override_missing_keys: Option<OverrideMessage>,
+    /// The custom filters available on top of the built-in ones.
+    pub filters: Filters,
+    /// The delimiters of the tags, used when the template is compiled along with the options and for nested templates.
+    pub delimiters: Delimiters,
+    /// The escaping applied to every value written by a tag.
+    pub escaping: Escaping,
+    /// The number of nested templates (`{{{key}}}`) that can be expanded inside each other, `32` by default.
+    pub max_nesting_depth: usize,
+    /// The number of bytes a template can write, unlimited when `None`.
+    pub max_output_bytes: Option<usize>,
+    /// The number of tags, block checks and loop items a template can render, including the ones of nested templates, unlimited when `None`.
+    pub max_substitutions: Option<usize>,
+    /// The number of mirror operators `*` a tag can use, unlimited when `None`.
+    pub max_mirror_depth: Option<usize>,
+    /// The number of blocks that can be opened inside each other, including the blocks around a nested template, `64` by default.
+    pub max_block_depth: usize,
+}
+
+impl StringTemplaterOptions {
+    /// The policy for the missing keys, read from the deprecated fields when `safe_parse` is set.
+    #[allow(deprecated)]
+    pub(crate) fn missing_key_policy(&self) -> Cow<'_, MissingKeyPolicy> {
+        if !self.safe_parse {
+            return Cow::Borrowed(&self.missing_keys);
+        }
+        Cow::Owned(
+            match (self.display_missing_keys, &self.override_missing_keys) {
+                (false, _) => MissingKeyPolicy::Remove,
+                (true, Some(message)) => MissingKeyPolicy::Placeholder(Arc::clone(message)),
+                (true, None) => MissingKeyPolicy::default_placeholder(),
+            },
+        )
+    }
+}
+
+impl Default for StringTemplaterOptions {
+    #[allow(deprecated)]
+    fn default() -> Self {
+        StringTemplaterOptions {
+            missing_keys: MissingKeyPolicy::default(),
+            safe_parse: false,
+            display_missing_keys: false,
+            override_missing_keys: None,
+            filters: Filters::default(),
+            delimiters: Delimiters::default(),
+            escaping: Escaping::default(),
+            max_nesting_depth: 32,
+            max_output_bytes: None,
+            max_substitutions: None,
+            max_mirror_depth: None,
+            max_block_depth: DEFAULT_MAX_BLOCK_DEPTH,
+        }
+    }
+}
+
+impl fmt::Debug for StringTemplaterOptions {
+    #[allow(deprecated)]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StringTemplaterOptions")
+            .field("missing_keys", &self.missing_keys)
+            .field("safe_parse", &self.safe_parse)
+            .field("display_missing_keys", &self.display_missing_keys)
+            .field(
+                "override_missing_keys",
+                &self.override_missing_keys.as_ref().map(|_| ".."),
+            )
+            .field("filters", &self.filters)
+            .field("delimiters", &self.delimiters)
+            .field("escaping", &self.escaping)
+            .field("max_nesting_depth", &self.max_nesting_depth)
+            .field("max_output_bytes", &self.max_output_bytes)
+            .field("max_substitutions", &self.max_substitutions)
+            .field("max_mirror_depth", &self.max_mirror_depth)
+            .field("max_block_depth", &self.max_block_depth)
+            .finish()
+    }
 }
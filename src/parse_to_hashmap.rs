@@ -16,7 +16,7 @@ pub fn parse_to_hashmap<T: ?Sized + Serialize>(
     }
 }
 
-fn flatten(prefix: &str, value: &serde_json::Value, map: &mut HashMap<String, String>) {
+pub(crate) fn flatten(prefix: &str, value: &serde_json::Value, map: &mut HashMap<String, String>) {
     match value {
         serde_json::Value::Object(obj) => {
             for (k, v) in obj {
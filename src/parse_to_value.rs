@@ -0,0 +1,10 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::errors::StringTemplaterError;
+
+/// Serialize a value into a typed `Value` tree the templates can be rendered with.
+/// Unlike `parse_to_hashmap`, numbers, booleans, `null`, arrays and objects keep their type.
+pub fn parse_to_value<T: ?Sized + Serialize>(value: &T) -> Result<Value, StringTemplaterError> {
+    serde_json::to_value(value).map_err(|err| StringTemplaterError::SerializeError(err.to_string()))
+}
@@ -0,0 +1,12 @@
+/// The location of a tag inside a string template.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    /// Byte offset of the first character of the tag.
+    pub start: usize,
+    /// Byte offset right after the last character of the tag.
+    pub end: usize,
+    /// Line of the first character of the tag, starting at 1.
+    pub line: usize,
+    /// Column of the first character of the tag in characters, starting at 1.
+    pub column: usize,
+}
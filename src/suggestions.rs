@@ -0,0 +1,42 @@
+/// The maximum number of suggestions attached to an unknown field.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Find the keys closest to a missing key, from the closest to the farthest.
+/// A key is close enough when it differs by at most a third of the length of the missing key.
+pub(crate) fn suggest<'a>(key: &str, candidates: impl Iterator<Item = &'a str>) -> Box<[String]> {
+    let key: Vec<char> = key.chars().collect();
+    let max_distance = (key.len() / 3).max(1);
+    let mut suggestions: Vec<(usize, &str)> = candidates
+        .filter_map(|candidate| {
+            let distance = edit_distance(&key, &candidate.chars().collect::<Vec<char>>());
+            (distance <= max_distance).then_some((distance, candidate))
+        })
+        .collect();
+    suggestions.sort_unstable();
+    suggestions.dedup();
+    suggestions
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, candidate)| candidate.to_string())
+        .collect()
+}
+
+/// The number of insertions, deletions, substitutions and transpositions of adjacent characters to go from `a` to `b`.
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut previous_row: Vec<usize> = Vec::new();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let before_row = std::mem::replace(&mut previous_row, row);
+        row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            row[j] = (previous_row[j] + 1)
+                .min(row[j - 1] + 1)
+                .min(previous_row[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(before_row[j - 2] + 1);
+            }
+        }
+    }
+    row[b.len()]
+}
@@ -0,0 +1,289 @@
+use std::{fmt, io};
+
+use crate::{
+    data_source::DataSource,
+    delimiters::Delimiters,
+    errors::StringTemplaterError,
+    filters::Arg,
+    io_writer::IoWriter,
+    key_reference::{KeyKind, KeyReference},
+    span::Span,
+    template_parser::{parse_template, parse_template_recovering, DEFAULT_MAX_BLOCK_DEPTH},
+    template_renderer::Renderer,
+    StringTemplaterOptions,
+};
+
+/// A string template compiled once into a tree of nodes.
+/// Compiling a template does all the scanning work up front, so rendering it afterward only does the data lookups.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+/// A single piece of a compiled template.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    /// Literal text, with the escape sequences already resolved.
+    Text(String),
+    /// A string interpolation such as `{{key}}`, `{{*key}}` or `{{{key}}}`.
+    Tag(Tag),
+    /// A conditional block such as `{{#if key}} ... {{else}} ... {{/if}}`.
+    If(IfBlock),
+    /// A loop over an array such as `{{#each items as item}} ... {{else}} ... {{/each}}`.
+    Each(EachBlock),
+}
+
+/// A key lookup inside a compiled template.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tag {
+    /// The name of the key to look for.
+    pub key: String,
+    /// The keys to look for in order when the key doesn't exist (`{{key ?? other_key}}`).
+    pub fallbacks: Vec<String>,
+    /// The literal used when neither the key nor its fallbacks exist (`{{key ?? "default"}}`).
+    pub default: Option<String>,
+    /// The filters applied one after another to the value (`{{key | upper | trim}}`).
+    pub filters: Vec<FilterCall>,
+    /// The number of mirror operators `*` applied to the key, `0` for a plain lookup.
+    pub mirror_depth: usize,
+    /// Whether the value found is itself used as a template (`{{{key}}}`).
+    pub nested: bool,
+    /// Whether the value is written without the escaping of the options (`{{&key}}`).
+    pub unescaped: bool,
+    /// The whole tag as written in the template.
+    pub source: String,
+    /// The location of the whole tag inside the template.
+    pub span: Span,
+}
+
+/// A filter applied to the value of a tag, such as `upper` or `truncate(10)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterCall {
+    /// The name of the filter.
+    pub name: String,
+    /// The arguments given between parentheses.
+    pub args: Vec<Arg>,
+}
+
+/// A conditional block, rendering the nodes of the first branch whose key is truthy.
+/// A key is falsy when it doesn't exist or when its value is `null`, `false`, zero, or an empty string, array or object.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IfBlock {
+    /// The branches `{{#if key}}` then `{{else if key}}`, in order.
+    pub branches: Vec<IfBranch>,
+    /// The nodes rendered when no branch is taken (`{{else}}`).
+    pub otherwise: Vec<Node>,
+    /// The location of the whole block inside the template, up to its `{{/if}}`.
+    pub span: Span,
+}
+
+/// A branch of a conditional block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IfBranch {
+    /// The key deciding whether the branch is taken.
+    pub key: String,
+    /// The nodes rendered when the branch is taken.
+    pub nodes: Vec<Node>,
+    /// The location of the `{{#if key}}` or `{{else if key}}` tag inside the template.
+    pub span: Span,
+}
+
+/// A loop block, rendering its nodes once for every item of an array.
+/// Inside the block, the variable gives the path of the current item (`{{item.name}}`), while `{{@index}}`, `{{@first}}` and `{{@last}}` give its position.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EachBlock {
+    /// The key of the array to walk.
+    pub key: String,
+    /// The name given to the current item.
+    pub variable: String,
+    /// The nodes rendered for every item.
+    pub nodes: Vec<Node>,
+    /// The nodes rendered when the array is empty or missing (`{{else}}`).
+    pub otherwise: Vec<Node>,
+    /// The location of the whole block inside the template, up to its `{{/each}}`.
+    pub span: Span,
+}
+
+impl Template {
+    /// Compile a string template into a reusable `Template`.
+    pub fn compile(template_str: &str) -> Result<Template, StringTemplaterError> {
+        Template::compile_with_delimiters(template_str, &Delimiters::default())
+    }
+    /// Compile a string template whose tags use other delimiters than `{{` and `}}`.
+    pub fn compile_with_delimiters(
+        template_str: &str,
+        delimiters: &Delimiters,
+    ) -> Result<Template, StringTemplaterError> {
+        Ok(Template {
+            nodes: parse_template(template_str, delimiters, 0, DEFAULT_MAX_BLOCK_DEPTH)?,
+        })
+    }
+    /// Compile a string template using the delimiters and the block depth limit of the options.
+    pub fn compile_with_options(
+        template_str: &str,
+        option: &StringTemplaterOptions,
+    ) -> Result<Template, StringTemplaterError> {
+        Ok(Template {
+            nodes: parse_template(template_str, &option.delimiters, 0, option.max_block_depth)?,
+        })
+    }
+    /// Compile a string template, keeping the faulty tags as text and returning their errors.
+    pub(crate) fn compile_recovering(
+        template_str: &str,
+        delimiters: &Delimiters,
+        max_block_depth: usize,
+    ) -> (Template, Vec<StringTemplaterError>) {
+        let (nodes, errors) =
+            parse_template_recovering(template_str, delimiters, 0, max_block_depth);
+        (Template { nodes }, errors)
+    }
+    /// The nodes the template is made of.
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+    /// List every key referenced by the template, in order of appearance.
+    /// Keys reached through mirrors or nested templates depend on the datas, so only the key written in the tag is listed and flagged by `KeyReference::reaches_dynamic_keys`.
+    /// Keys using the variable of a loop are listed as paths inside the first item of the array, such as `items.0.name` for `{{item.name}}`.
+    pub fn referenced_keys(&self) -> Vec<KeyReference> {
+        let mut references = Vec::new();
+        collect_references(&self.nodes, &mut Vec::new(), &mut references);
+        references
+    }
+    /// Inject the values of a data source, such as a hashmap, in the compiled template.
+    /// The delimiters of the options are used for the nested templates.
+    pub fn render<D: DataSource + ?Sized>(
+        &self,
+        data: &D,
+        option: &StringTemplaterOptions,
+    ) -> Result<String, StringTemplaterError> {
+        let mut result = String::new();
+        self.render_to_fmt(data, option, &mut result)?;
+        Ok(result)
+    }
+    /// Inject the values of a data source, such as a hashmap, in the compiled template, writing the output straight into `out`.
+    /// When an error occurs, the output written so far stays in `out`.
+    pub fn render_to_fmt<D: DataSource + ?Sized>(
+        &self,
+        data: &D,
+        option: &StringTemplaterOptions,
+        out: &mut impl fmt::Write,
+    ) -> Result<(), StringTemplaterError> {
+        Renderer::new(data, option).render(&self.nodes, out)
+    }
+    /// Inject the values of a data source, such as a hashmap, in the compiled template, writing the output straight into `out`.
+    /// When an error occurs, the output written so far stays in `out`, and IO errors are returned as `StringTemplaterError::WriteError`.
+    pub fn render_to_io<D: DataSource + ?Sized>(
+        &self,
+        data: &D,
+        option: &StringTemplaterOptions,
+        out: &mut impl io::Write,
+    ) -> Result<(), StringTemplaterError> {
+        let mut writer = IoWriter::new(out);
+        self.render_to_fmt(data, option, &mut writer)
+            .map_err(|err| writer.restore_error(err))
+    }
+    /// Render a string template without stopping at the first error.
+    /// Every faulty tag is reported and rendered as best as possible: a tag missing its curvy brackets is kept as text while an unknown field is left empty.
+    /// Returns the rendered string along with all the errors found, the template is valid when there's none.
+    pub fn diagnose<D: DataSource + ?Sized>(
+        template_str: &str,
+        data: &D,
+        option: &StringTemplaterOptions,
+    ) -> (String, Vec<StringTemplaterError>) {
+        let (template, mut errors) =
+            Template::compile_recovering(template_str, &option.delimiters, option.max_block_depth);
+        let mut renderer = Renderer::recovering(data, option);
+        let mut result = String::new();
+        // A recovering renderer only stops when a limit of the options is exceeded
+        let aborted = renderer.render(&template.nodes, &mut result).err();
+        errors.extend(renderer.into_diagnostics());
+        errors.extend(aborted);
+        errors.sort_by_key(|err| err.root_span().map(|span| span.start));
+        (result, errors)
+    }
+}
+
+/// `variables` holds the variable of each loop around the nodes, along with the path of its first item.
+fn collect_references(
+    nodes: &[Node],
+    variables: &mut Vec<(String, String)>,
+    references: &mut Vec<KeyReference>,
+) {
+    for node in nodes {
+        match node {
+            Node::Text(_) => {}
+            Node::Tag(tag) => {
+                let kind = match (tag.nested, tag.mirror_depth) {
+                    (true, mirror_depth) => KeyKind::Nested { mirror_depth },
+                    (false, 0) => KeyKind::Plain,
+                    (false, depth) => KeyKind::Mirror { depth },
+                };
+                for (i, key) in std::iter::once(&tag.key).chain(&tag.fallbacks).enumerate() {
+                    let reference = KeyReference {
+                        key: key.clone(),
+                        kind,
+                        fallback: i > 0,
+                        optional: tag.default.is_some(),
+                        span: tag.span,
+                    };
+                    push_reference(reference, variables, references);
+                }
+            }
+            Node::If(block) => {
+                for branch in &block.branches {
+                    let reference = KeyReference {
+                        key: branch.key.clone(),
+                        kind: KeyKind::Condition,
+                        fallback: false,
+                        optional: true,
+                        span: branch.span,
+                    };
+                    push_reference(reference, variables, references);
+                    collect_references(&branch.nodes, variables, references);
+                }
+                collect_references(&block.otherwise, variables, references);
+            }
+            Node::Each(block) => {
+                let key = scoped_key(&block.key, variables);
+                let reference = KeyReference {
+                    key: block.key.clone(),
+                    kind: KeyKind::Loop,
+                    fallback: false,
+                    optional: true,
+                    span: block.span,
+                };
+                push_reference(reference, variables, references);
+                variables.push((block.variable.clone(), format!("{}.0", key)));
+                collect_references(&block.nodes, variables, references);
+                variables.pop();
+                collect_references(&block.otherwise, variables, references);
+            }
+        }
+    }
+}
+
+/// Record a reference, replacing the variables of the loops around it in its key.
+fn push_reference(
+    mut reference: KeyReference,
+    variables: &[(String, String)],
+    references: &mut Vec<KeyReference>,
+) {
+    // The position of the current item isn't a key of the datas
+    if !variables.is_empty() && matches!(reference.key.as_str(), "@index" | "@first" | "@last") {
+        return;
+    }
+    reference.key = scoped_key(&reference.key, variables);
+    references.push(reference);
+}
+
+/// Replace the variable of the innermost loop used by a key with the path of its item.
+fn scoped_key(key: &str, variables: &[(String, String)]) -> String {
+    for (variable, path) in variables.iter().rev() {
+        if let Some(rest) = key.strip_prefix(variable.as_str()) {
+            if rest.is_empty() || rest.starts_with('.') {
+                return format!("{}{}", path, rest);
+            }
+        }
+    }
+    key.to_string()
+}
@@ -1,14 +1,77 @@
 use serde::Serialize;
-use std::collections::HashMap;
+use serde_json::Value;
+use std::{borrow::Cow, collections::HashMap, fmt, io, sync::Arc};
 
 use crate::{
-    generate_with_options, parse_to_hashmap, OverrideMessage, StringTemplaterError,
-    StringTemplaterOptions,
+    parse_to_hashmap, Arg, DataSource, Delimiters, Escaping, MissingKeyPolicy, OverrideMessage,
+    StringTemplaterError, StringTemplaterOptions, Template,
 };
 
+/// A data source shared between the clones of a builder.
+type SharedDataSource = Arc<dyn DataSource + Send + Sync>;
+
+#[derive(Clone)]
 pub struct TemplateBuilder {
     template_values: HashMap<String, String>,
+    data_sources: Vec<SharedDataSource>,
     options: StringTemplaterOptions,
+    /// The policy of the missing keys when they aren't errors, restored by `set_safe_parse(true)`.
+    safe_missing_keys: MissingKeyPolicy,
+}
+
+/// The values of a builder, then its data sources in order of insertion.
+struct BuilderDatas<'a> {
+    template_values: &'a HashMap<String, String>,
+    data_sources: &'a [SharedDataSource],
+}
+
+impl DataSource for BuilderDatas<'_> {
+    fn get(&self, key: &str) -> Option<Cow<'_, str>> {
+        match self.template_values.get(key) {
+            Some(value) => Some(Cow::Borrowed(value)),
+            None => self.data_sources.iter().find_map(|source| source.get(key)),
+        }
+    }
+    fn get_value(&self, key: &str) -> Option<Cow<'_, Value>> {
+        match DataSource::get_value(self.template_values, key) {
+            Some(value) => Some(value),
+            None => self
+                .data_sources
+                .iter()
+                .find_map(|source| source.get_value(key)),
+        }
+    }
+    fn keys(&self) -> Vec<String> {
+        let mut keys = self
+            .template_values
+            .keys()
+            .cloned()
+            .collect::<Vec<String>>();
+        for source in self.data_sources {
+            keys.extend(source.keys());
+        }
+        keys
+    }
+    fn array_lens(&self) -> HashMap<String, usize> {
+        let mut lens = self.template_values.array_lens();
+        for source in self.data_sources {
+            for (path, len) in source.array_lens() {
+                lens.entry(path).or_insert(len);
+            }
+        }
+        lens
+    }
+}
+
+impl fmt::Debug for TemplateBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TemplateBuilder")
+            .field("template_values", &self.template_values)
+            .field("data_sources", &self.data_sources.len())
+            .field("options", &self.options)
+            .field("safe_missing_keys", &self.safe_missing_keys)
+            .finish()
+    }
 }
 
 impl Default for TemplateBuilder {
@@ -21,11 +84,9 @@ impl TemplateBuilder {
     pub fn new() -> Self {
         TemplateBuilder {
             template_values: HashMap::new(),
-            options: StringTemplaterOptions {
-                safe_parse: false,
-                display_missing_keys: false,
-                override_missing_keys: None,
-            },
+            data_sources: Vec::new(),
+            options: StringTemplaterOptions::default(),
+            safe_missing_keys: MissingKeyPolicy::Remove,
         }
     }
     /// Insert a key / value for the template to use.
@@ -38,6 +99,11 @@ impl TemplateBuilder {
             self.insert(key, value);
         }
     }
+    /// Insert a data source for the template to look up the keys missing from the inserted values.
+    /// Data sources are looked up in the order they were inserted.
+    pub fn insert_data_source(&mut self, data_source: impl DataSource + Send + Sync + 'static) {
+        self.data_sources.push(Arc::new(data_source));
+    }
     /// Insert a collection of key / value from the struct for the template to use.
     /// This method can fail while parsing and let you handle the error.
     pub fn insert_struct<T: ?Sized + Serialize>(
@@ -52,37 +118,69 @@ impl TemplateBuilder {
             Err(err) => Err(err),
         }
     }
-    /// Display the missing key fields.
+    /// Choose what to write in place of a tag whose key doesn't exist.
+    pub fn set_missing_key_policy(&mut self, policy: MissingKeyPolicy) {
+        if !matches!(policy, MissingKeyPolicy::Error) {
+            self.safe_missing_keys = policy.clone();
+        }
+        self.options.missing_keys = policy;
+    }
+    /// The policy for the tags whose key doesn't exist.
+    pub fn missing_key_policy(&self) -> &MissingKeyPolicy {
+        &self.options.missing_keys
+    }
+    /// Display the missing key fields, keeping the message of any placeholder already set.
     pub fn display_missing_keys(&mut self) {
-        self.options = StringTemplaterOptions {
-            safe_parse: self.options.safe_parse,
-            display_missing_keys: true,
-            override_missing_keys: self.options.override_missing_keys.take(),
+        if !matches!(self.safe_missing_keys, MissingKeyPolicy::Placeholder(_)) {
+            self.set_safe_missing_keys(MissingKeyPolicy::default_placeholder());
         }
     }
     /// Hide missing key fields.
     pub fn hide_missing_keys(&mut self) {
-        self.options = StringTemplaterOptions {
-            safe_parse: self.options.safe_parse,
-            display_missing_keys: false,
-            override_missing_keys: self.options.override_missing_keys.take(),
-        }
+        self.set_safe_missing_keys(MissingKeyPolicy::Remove);
     }
     /// Override the missing key fields using a specific method.
     pub fn override_missing_keys_message(&mut self, override_missing_keys: OverrideMessage) {
-        self.options = StringTemplaterOptions {
-            safe_parse: self.options.safe_parse,
-            display_missing_keys: self.options.display_missing_keys,
-            override_missing_keys: Some(override_missing_keys),
+        self.set_safe_missing_keys(MissingKeyPolicy::Placeholder(override_missing_keys));
+    }
+    /// Choose how the missing keys are handled when safe parse is on, applying it right away only if it already is.
+    fn set_safe_missing_keys(&mut self, policy: MissingKeyPolicy) {
+        if !matches!(self.options.missing_keys, MissingKeyPolicy::Error) {
+            self.options.missing_keys = policy.clone();
         }
+        self.safe_missing_keys = policy;
     }
-    /// Safe parse allow you to ignore errors comming from missing keys.
+    /// Safe parse allow you to ignore errors comming from missing keys, handling them the way they were last displayed or hidden, removing them by default.
+    /// Turning it off makes missing keys errors again.
     pub fn set_safe_parse(&mut self, safe_parse: bool) {
-        self.options = StringTemplaterOptions {
-            safe_parse,
-            display_missing_keys: self.options.display_missing_keys,
-            override_missing_keys: self.options.override_missing_keys.take(),
-        }
+        self.options.missing_keys = if safe_parse {
+            self.safe_missing_keys.clone()
+        } else {
+            MissingKeyPolicy::Error
+        };
+    }
+    /// Use other delimiters for the tags of the templates, such as `<%` and `%>`.
+    /// Fails when the delimiters can't be used, leaving the current ones.
+    pub fn set_delimiters(&mut self, open: &str, close: &str) -> Result<(), StringTemplaterError> {
+        self.options.delimiters = Delimiters::new(open, close)?;
+        Ok(())
+    }
+    /// Choose the escaping applied to every value written by a tag, such as `Escaping::Html` for `HTML` outputs.
+    pub fn set_escaping(&mut self, escaping: Escaping) {
+        self.options.escaping = escaping;
+    }
+    /// The escaping applied to every value written by a tag.
+    pub fn escaping(&self) -> Escaping {
+        self.options.escaping
+    }
+    /// Register a custom filter the templates can use, replacing any filter of the same name.
+    /// The error returned by the filter is reported as a `StringTemplaterError::FilterError`.
+    pub fn register_filter<F, E>(&mut self, name: &str, filter: F)
+    where
+        F: Fn(&str, &[Arg]) -> Result<String, E> + Send + Sync + 'static,
+        E: fmt::Display,
+    {
+        self.options.filters.register(name, filter);
     }
     /// Insert a collection of key / value from the struct for the template to use.
     /// This method either insert datas if it succeed or do nothing.
@@ -93,6 +191,47 @@ impl TemplateBuilder {
     }
     /// Build the chosen template using the values of the builder.
     pub fn build(&self, template_str: &str) -> Result<String, StringTemplaterError> {
-        generate_with_options(template_str, &self.template_values, &self.options)
+        self.build_template(&Template::compile_with_options(
+            template_str,
+            &self.options,
+        )?)
+    }
+    /// Build an already compiled template using the values of the builder.
+    pub fn build_template(&self, template: &Template) -> Result<String, StringTemplaterError> {
+        template.render(&self.datas(), &self.options)
+    }
+    /// Build the chosen template using the values of the builder, writing the output straight into `out`.
+    pub fn build_to_fmt(
+        &self,
+        template_str: &str,
+        out: &mut impl fmt::Write,
+    ) -> Result<(), StringTemplaterError> {
+        Template::compile_with_options(template_str, &self.options)?.render_to_fmt(
+            &self.datas(),
+            &self.options,
+            out,
+        )
+    }
+    /// Build the chosen template using the values of the builder, writing the output straight into `out`.
+    pub fn build_to_io(
+        &self,
+        template_str: &str,
+        out: &mut impl io::Write,
+    ) -> Result<(), StringTemplaterError> {
+        Template::compile_with_options(template_str, &self.options)?.render_to_io(
+            &self.datas(),
+            &self.options,
+            out,
+        )
+    }
+    /// Build the chosen template using the values of the builder, collecting every error instead of stopping at the first one.
+    pub fn diagnose(&self, template_str: &str) -> (String, Vec<StringTemplaterError>) {
+        Template::diagnose(template_str, &self.datas(), &self.options)
+    }
+    fn datas(&self) -> BuilderDatas<'_> {
+        BuilderDatas {
+            template_values: &self.template_values,
+            data_sources: &self.data_sources,
+        }
     }
 }
@@ -0,0 +1,750 @@
+use crate::{
+    delimiters::Delimiters,
+    errors::StringTemplaterError,
+    filters::Arg,
+    limit::Limit,
+    span::Span,
+    template::{EachBlock, FilterCall, IfBlock, IfBranch, Node, Tag},
+};
+
+/// The number of blocks that can be opened inside each other when the options don't say otherwise.
+pub(crate) const DEFAULT_MAX_BLOCK_DEPTH: usize = 64;
+
+/// Scan a string template and turn it into a list of nodes.
+/// `open_blocks` is the number of blocks already opened around the template, out of `max_block_depth`.
+pub(crate) fn parse_template(
+    template_str: &str,
+    delimiters: &Delimiters,
+    open_blocks: usize,
+    max_block_depth: usize,
+) -> Result<Vec<Node>, StringTemplaterError> {
+    Parser::new(template_str, delimiters, open_blocks, max_block_depth, None).parse_all()
+}
+
+/// Scan a string template and turn it into a list of nodes, keeping the faulty tags as text.
+/// An exceeded limit still stops the parsing, and is returned last without any node.
+pub(crate) fn parse_template_recovering(
+    template_str: &str,
+    delimiters: &Delimiters,
+    open_blocks: usize,
+    max_block_depth: usize,
+) -> (Vec<Node>, Vec<StringTemplaterError>) {
+    let mut parser = Parser::new(
+        template_str,
+        delimiters,
+        open_blocks,
+        max_block_depth,
+        Some(Vec::new()),
+    );
+    let parsed = parser.parse_all();
+    let mut errors = parser.errors.unwrap_or_default();
+    match parsed {
+        Ok(nodes) => (nodes, errors),
+        Err(err) => {
+            errors.push(err);
+            (Vec::new(), errors)
+        }
+    }
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    delimiters: &'a Delimiters,
+    position: usize,
+    line: usize,
+    column: usize,
+    /// The errors met so far, when the parsing goes on after an error.
+    errors: Option<Vec<StringTemplaterError>>,
+    /// Whether the last tag was closed by `-}}`, so the whitespace following it is skipped.
+    trim_whitespace: bool,
+    /// The number of blocks opened around the current position.
+    block_depth: usize,
+    /// The number of blocks that can be opened inside each other.
+    max_block_depth: usize,
+}
+
+/// A tag ending the current section of a block.
+enum BlockEnd {
+    /// `{{else}}`, or `{{else if key}}` along with its key.
+    Else {
+        condition: Option<String>,
+        span: Span,
+    },
+    /// `{{/name}}`.
+    Close { name: String, span: Span },
+}
+
+impl<'a> Parser<'a> {
+    fn new(
+        source: &'a str,
+        delimiters: &'a Delimiters,
+        block_depth: usize,
+        max_block_depth: usize,
+        errors: Option<Vec<StringTemplaterError>>,
+    ) -> Self {
+        Parser {
+            source,
+            delimiters,
+            position: 0,
+            line: 1,
+            column: 1,
+            errors,
+            trim_whitespace: false,
+            block_depth,
+            max_block_depth,
+        }
+    }
+    /// Parse the whole template.
+    fn parse_all(&mut self) -> Result<Vec<Node>, StringTemplaterError> {
+        self.parse_nodes(false).map(|(nodes, _)| nodes)
+    }
+    /// Parse nodes up to the end of the template or, inside a block, up to the tag ending the current section.
+    fn parse_nodes(
+        &mut self,
+        in_block: bool,
+    ) -> Result<(Vec<Node>, Option<BlockEnd>), StringTemplaterError> {
+        let mut nodes = Vec::new();
+        let mut text = String::new();
+        loop {
+            if std::mem::take(&mut self.trim_whitespace) {
+                self.skip_whitespace();
+            }
+            let start = self.location();
+            if self.eat_str(self.delimiters.open()) {
+                // Opened by `{{-` or `{{{-`, the tag skips the whitespace before it
+                let rest = &self.source[self.position..];
+                let rest = rest
+                    .strip_prefix(self.delimiters.nested_open())
+                    .unwrap_or(rest);
+                if rest.starts_with('-') {
+                    text.truncate(text.trim_end().len());
+                }
+                if let Some(content) = self.parse_raw_block() {
+                    text.push_str(content);
+                    continue;
+                }
+                if self.tag_body().starts_with('!') {
+                    if let Err(err) = self.skip_comment(start) {
+                        self.report(err)?;
+                        text.push_str(&self.source[start.start..self.position]);
+                    }
+                    continue;
+                }
+                if !text.is_empty() {
+                    nodes.push(Node::Text(std::mem::take(&mut text)));
+                }
+                let parsed = if self.tag_body().starts_with('#') {
+                    self.parse_block(start)
+                } else if self.tag_body().starts_with('/') || in_block && self.at_else() {
+                    match self.parse_block_end(start) {
+                        Ok(end) if in_block => return Ok((nodes, Some(end))),
+                        Ok(_) => Err(self.invalid_tag_at(self.span_from(start))),
+                        Err(err) => Err(err),
+                    }
+                } else {
+                    self.parse_tag(start).map(Node::Tag)
+                };
+                match parsed {
+                    Ok(node) => nodes.push(node),
+                    Err(err) => {
+                        self.report(err)?;
+                        text.push_str(&self.source[start.start..self.position]);
+                    }
+                }
+                continue;
+            }
+            let Some(c) = self.next() else {
+                break;
+            };
+            match c {
+                // `\` escapes a delimiter, and `\\` writes a single `\` right before one
+                '\\' => {
+                    let before = &self.source[..self.position - 1];
+                    let after = &self.source[self.position..];
+                    if self.delimiters.escapes(before, after) {
+                        text.extend(self.next());
+                    } else if let Some(rest) = after.strip_prefix('\\') {
+                        self.next();
+                        text.push(c);
+                        if !self.delimiters.escapes("", rest) {
+                            text.push(c);
+                        }
+                    } else {
+                        text.push(c);
+                    }
+                }
+                _ => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            nodes.push(Node::Text(text));
+        }
+        Ok((nodes, None))
+    }
+    /// Either record the error or stop the parsing with it. Exceeded limits always stop it.
+    fn report(&mut self, err: StringTemplaterError) -> Result<(), StringTemplaterError> {
+        match &mut self.errors {
+            Some(errors) if !matches!(err, StringTemplaterError::LimitExceeded { .. }) => {
+                errors.push(err);
+                Ok(())
+            }
+            _ => Err(err),
+        }
+    }
+    /// Skip a comment `{{! ... }}`, or `{{!-- ... --}}` which may contain `}}`, right after its opening `{{`.
+    fn skip_comment(&mut self, start: Span) -> Result<(), StringTemplaterError> {
+        self.eat_opening_marker();
+        self.next();
+        let close = self.delimiters.close();
+        let closing = if self.eat_str("--") {
+            format!("--{}", close)
+        } else {
+            close.to_string()
+        };
+        let content = self.position;
+        while !self.eat_str(&closing) {
+            if self.next().is_none() {
+                return Err(StringTemplaterError::MissingCurvyBracket {
+                    expected: close.chars().count(),
+                    found: 0,
+                    key: self.source[content..].trim().to_string(),
+                    span: self.span_from(start),
+                    expanded_from: Vec::new(),
+                });
+            }
+        }
+        self.trim_whitespace = self.source[..self.position - closing.len()].ends_with('-');
+        Ok(())
+    }
+    /// Parse a raw block `{{raw}} ... {{/raw}}` right after its opening `{{`, returning its content exactly as written.
+    /// Without a `{{/raw}}` to close it, `{{raw}}` is a regular tag and `None` is returned.
+    fn parse_raw_block(&mut self) -> Option<&'a str> {
+        let source = self.source;
+        let open = self.delimiters.open();
+        let (content_start, trim_start) = self.match_tag(self.position, "raw")?;
+        let (content_end, end, trim_after) =
+            source[content_start..]
+                .match_indices(open)
+                .find_map(|(i, _)| {
+                    let content_end = content_start + i;
+                    let (end, trim_after) = self.match_tag(content_end + open.len(), "/raw")?;
+                    Some((content_end, end, trim_after))
+                })?;
+        let mut content = &source[content_start..content_end];
+        if trim_start {
+            content = content.trim_start();
+        }
+        if source[content_end + open.len()..].starts_with('-') {
+            content = content.trim_end();
+        }
+        while self.position < end {
+            self.next();
+        }
+        self.trim_whitespace = trim_after;
+        Some(content)
+    }
+    /// Match a tag made of a single word, such as `raw` or `/raw`, at a position right after its opening `{{`.
+    /// Returns the position after the tag and whether it's closed by the whitespace control marker `-}}`.
+    fn match_tag(&self, position: usize, word: &str) -> Option<(usize, bool)> {
+        let rest = &self.source[position..];
+        let rest = match rest.strip_prefix('-') {
+            Some(rest) => rest.trim_start(),
+            None => rest,
+        };
+        let rest = rest.strip_prefix(word)?.trim_start();
+        let (rest, trim_after) = match rest.strip_prefix('-') {
+            Some(rest) => (rest, true),
+            None => (rest, false),
+        };
+        let rest = rest.strip_prefix(self.delimiters.close())?;
+        Some((self.source.len() - rest.len(), trim_after))
+    }
+    /// Parse a block such as `{{#if key}} ... {{/if}}` right after its opening `{{`.
+    fn parse_block(&mut self, start: Span) -> Result<Node, StringTemplaterError> {
+        if self.block_depth >= self.max_block_depth {
+            return Err(StringTemplaterError::LimitExceeded {
+                limit: Limit::BlockDepth(self.max_block_depth),
+            });
+        }
+        self.block_depth += 1;
+        let block = self.parse_block_content(start);
+        self.block_depth -= 1;
+        block
+    }
+    /// Parse the rest of a block right after its opening `{{`.
+    fn parse_block_content(&mut self, start: Span) -> Result<Node, StringTemplaterError> {
+        self.eat_opening_marker();
+        self.next();
+        match self.parse_word().as_str() {
+            "if" => {
+                let key = self.parse_block_key(start)?;
+                let opening = self.span_from(start);
+                self.parse_if(key, opening).map(Node::If)
+            }
+            "each" => {
+                let header = self.parse_block_key(start)?;
+                let opening = self.span_from(start);
+                match header.split_whitespace().collect::<Vec<_>>()[..] {
+                    [key, "as", variable]
+                        if variable.chars().all(|c| c.is_alphanumeric() || c == '_') =>
+                    {
+                        self.parse_each(key.to_string(), variable.to_string(), opening)
+                            .map(Node::Each)
+                    }
+                    _ => Err(self.invalid_tag_at(opening)),
+                }
+            }
+            _ => Err(self.invalid_tag(start, false)),
+        }
+    }
+    /// Parse the branches of a conditional block right after its `{{#if key}}`.
+    fn parse_if(&mut self, key: String, opening: Span) -> Result<IfBlock, StringTemplaterError> {
+        let mut branches = Vec::new();
+        let mut otherwise = Vec::new();
+        let mut condition = Some((key, opening));
+        let mut in_else = false;
+        loop {
+            let (nodes, end) = self.parse_nodes(true)?;
+            match condition.take() {
+                Some((key, span)) => branches.push(IfBranch { key, nodes, span }),
+                None => otherwise.extend(nodes),
+            }
+            match end {
+                Some(BlockEnd::Else { span, .. }) if in_else => {
+                    let err = self.invalid_tag_at(span);
+                    self.report(err)?
+                }
+                Some(BlockEnd::Else {
+                    condition: Some(key),
+                    span,
+                }) => condition = Some((key, span)),
+                Some(BlockEnd::Else {
+                    condition: None, ..
+                }) => in_else = true,
+                Some(BlockEnd::Close { name, span }) if name == "if" => {
+                    return Ok(IfBlock {
+                        branches,
+                        otherwise,
+                        span: Span {
+                            end: span.end,
+                            ..opening
+                        },
+                    })
+                }
+                Some(BlockEnd::Close { span, .. }) => {
+                    let err = self.invalid_tag_at(span);
+                    self.report(err)?
+                }
+                None => {
+                    return Ok(IfBlock {
+                        branches,
+                        otherwise,
+                        span: self.unclosed_block("if", opening)?,
+                    })
+                }
+            }
+        }
+    }
+    /// Parse the sections of a loop block right after its `{{#each key as variable}}`.
+    fn parse_each(
+        &mut self,
+        key: String,
+        variable: String,
+        opening: Span,
+    ) -> Result<EachBlock, StringTemplaterError> {
+        let mut nodes = Vec::new();
+        let mut otherwise = Vec::new();
+        let mut in_else = false;
+        loop {
+            let (section, end) = self.parse_nodes(true)?;
+            if in_else {
+                otherwise.extend(section);
+            } else {
+                nodes.extend(section);
+            }
+            let span = match end {
+                Some(BlockEnd::Else {
+                    condition: None, ..
+                }) if !in_else => {
+                    in_else = true;
+                    continue;
+                }
+                Some(BlockEnd::Close { name, span }) if name == "each" => Span {
+                    end: span.end,
+                    ..opening
+                },
+                Some(BlockEnd::Else { span, .. } | BlockEnd::Close { span, .. }) => {
+                    let err = self.invalid_tag_at(span);
+                    self.report(err)?;
+                    continue;
+                }
+                None => self.unclosed_block("each", opening)?,
+            };
+            return Ok(EachBlock {
+                key,
+                variable,
+                nodes,
+                otherwise,
+                span,
+            });
+        }
+    }
+    /// Report a block reaching the end of the template, returning its span up to there.
+    fn unclosed_block(&mut self, block: &str, opening: Span) -> Result<Span, StringTemplaterError> {
+        self.report(StringTemplaterError::UnclosedBlock {
+            block: block.to_string(),
+            span: opening,
+            expanded_from: Vec::new(),
+        })?;
+        Ok(self.span_from(opening))
+    }
+    fn peek(&self) -> Option<char> {
+        self.source[self.position..].chars().next()
+    }
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+    /// An empty span at the current position.
+    fn location(&self) -> Span {
+        Span {
+            start: self.position,
+            end: self.position,
+            line: self.line,
+            column: self.column,
+        }
+    }
+    /// Extend a span started earlier up to the current position.
+    fn span_from(&self, start: Span) -> Span {
+        Span {
+            end: self.position,
+            ..start
+        }
+    }
+    fn eat_str(&mut self, expected: &str) -> bool {
+        if self.source[self.position..].starts_with(expected) {
+            for _ in expected.chars() {
+                self.next();
+            }
+            true
+        } else {
+            false
+        }
+    }
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.next();
+        }
+    }
+    /// Parse a tag right after its opening `{{`.
+    fn parse_tag(&mut self, start: Span) -> Result<Tag, StringTemplaterError> {
+        // Templating
+        let nested = self.eat(self.delimiters.nested_open());
+        let closing = self.delimiters.closing(nested);
+        let expected = closing.chars().count();
+        let trim_before = self.eat_opening_marker();
+        let unescaped = self.eat('&');
+
+        // Mirroring
+        let mut mirror_depth = 0;
+        while self.eat('*') {
+            mirror_depth += 1;
+        }
+
+        // Key looking, with the fallbacks separated by `??`
+        let mut keys = vec![self.parse_key()];
+        let mut default = None;
+        while self.eat_str("??") {
+            self.skip_whitespace();
+            if self.peek() == Some('"') {
+                match self.parse_literal() {
+                    Some(literal) => default = Some(literal),
+                    None => {
+                        return Err(StringTemplaterError::MissingCurvyBracket {
+                            expected,
+                            found: 0,
+                            key: keys.swap_remove(0),
+                            span: self.span_from(start),
+                            expanded_from: Vec::new(),
+                        })
+                    }
+                }
+                self.skip_whitespace();
+                break;
+            }
+            keys.push(self.parse_key());
+        }
+
+        // Filters
+        let mut filters = Vec::new();
+        while self.eat('|') {
+            match self.parse_filter_call() {
+                Some(filter) => filters.push(filter),
+                None => return Err(self.invalid_tag(start, nested)),
+            }
+        }
+
+        let trim_after = self.eat_closing_marker();
+        if keys.len() > 1 || default.is_some() || !filters.is_empty() || trim_before || trim_after {
+            for key in keys.iter_mut() {
+                *key = key.trim().to_string();
+            }
+            if keys.iter().any(String::is_empty)
+                || self
+                    .peek()
+                    .is_some_and(|c| c != self.delimiters.nested_close())
+            {
+                return Err(self.invalid_tag(start, nested));
+            }
+        }
+
+        // Error handling
+        for (found, c) in closing.chars().enumerate() {
+            if !self.eat(c) {
+                return Err(StringTemplaterError::MissingCurvyBracket {
+                    expected,
+                    found,
+                    key: keys.swap_remove(0),
+                    span: self.span_from(start),
+                    expanded_from: Vec::new(),
+                });
+            }
+        }
+
+        let key = keys.remove(0);
+        let span = self.span_from(start);
+        Ok(Tag {
+            key,
+            fallbacks: keys,
+            default,
+            filters,
+            mirror_depth,
+            nested,
+            unescaped,
+            source: self.source[span.start..span.end].to_string(),
+            span,
+        })
+    }
+    /// The rest of a tag right after its opening `{{`, past its whitespace control marker `-`.
+    fn tag_body(&self) -> &str {
+        let rest = &self.source[self.position..];
+        match rest.strip_prefix('-') {
+            Some(rest) => rest.trim_start(),
+            None => rest,
+        }
+    }
+    /// Eat the whitespace control marker `-` opening a tag, along with the whitespace after it.
+    fn eat_opening_marker(&mut self) -> bool {
+        let found = self.eat('-');
+        if found {
+            self.skip_whitespace();
+        }
+        found
+    }
+    /// Eat the whitespace control marker `-` closing a tag, so the whitespace following the tag is skipped.
+    fn eat_closing_marker(&mut self) -> bool {
+        let rest = &self.source[self.position..];
+        let found = rest
+            .strip_prefix('-')
+            .is_some_and(|rest| rest.starts_with(self.delimiters.nested_close()));
+        if found {
+            self.next();
+            self.trim_whitespace = true;
+        }
+        found
+    }
+    /// Whether the tag being parsed is an `{{else}}` or an `{{else if key}}`.
+    fn at_else(&self) -> bool {
+        self.tag_body().strip_prefix("else").is_some_and(|rest| {
+            rest.starts_with(|c: char| c == self.delimiters.nested_close() || c.is_whitespace())
+        })
+    }
+    /// Parse a tag ending a section of a block, such as `{{else}}` or `{{/if}}`, right after its opening `{{`.
+    fn parse_block_end(&mut self, start: Span) -> Result<BlockEnd, StringTemplaterError> {
+        self.eat_opening_marker();
+        if self.eat('/') {
+            let name = self.parse_word();
+            self.skip_whitespace();
+            self.eat_closing_marker();
+            if name.is_empty() || !self.eat_str(self.delimiters.close()) {
+                return Err(self.invalid_tag(start, false));
+            }
+            return Ok(BlockEnd::Close {
+                name,
+                span: self.span_from(start),
+            });
+        }
+        self.eat_str("else");
+        self.skip_whitespace();
+        let condition = match self.parse_word().as_str() {
+            "" => {
+                self.eat_closing_marker();
+                if !self.eat_str(self.delimiters.close()) {
+                    return Err(self.invalid_tag(start, false));
+                }
+                None
+            }
+            "if" => Some(self.parse_block_key(start)?),
+            _ => return Err(self.invalid_tag(start, false)),
+        };
+        Ok(BlockEnd::Else {
+            condition,
+            span: self.span_from(start),
+        })
+    }
+    /// Parse the key of a block tag such as `{{#if key}}` up to the end of the tag.
+    fn parse_block_key(&mut self, start: Span) -> Result<String, StringTemplaterError> {
+        if !self.peek().is_some_and(char::is_whitespace) {
+            return Err(self.invalid_tag(start, false));
+        }
+        let key = self.parse_key().trim().to_string();
+        self.eat_closing_marker();
+        if key.is_empty() || !self.eat_str(self.delimiters.close()) {
+            return Err(self.invalid_tag(start, false));
+        }
+        Ok(key)
+    }
+    /// Parse the name of a block or of a filter.
+    fn parse_word(&mut self) -> String {
+        let mut word = String::new();
+        while let Some(c) = self.peek().filter(|c| c.is_alphanumeric() || *c == '_') {
+            self.next();
+            word.push(c);
+        }
+        word
+    }
+    /// The error for a tag already parsed that isn't valid where it's written.
+    fn invalid_tag_at(&self, span: Span) -> StringTemplaterError {
+        StringTemplaterError::InvalidTag {
+            tag: self.source[span.start..span.end].to_string(),
+            span,
+            expanded_from: Vec::new(),
+        }
+    }
+    /// Parse a filter such as `upper` or `replace("a", "b")` right after its `|`.
+    /// Returns `None` when the filter is malformed.
+    fn parse_filter_call(&mut self) -> Option<FilterCall> {
+        self.skip_whitespace();
+        let name = self.parse_word();
+        if name.is_empty() {
+            return None;
+        }
+        self.skip_whitespace();
+        let mut args = Vec::new();
+        if self.eat('(') {
+            self.skip_whitespace();
+            if !self.eat(')') {
+                loop {
+                    args.push(self.parse_arg()?);
+                    self.skip_whitespace();
+                    if self.eat(')') {
+                        break;
+                    } else if !self.eat(',') {
+                        return None;
+                    }
+                    self.skip_whitespace();
+                }
+            }
+            self.skip_whitespace();
+        }
+        Some(FilterCall { name, args })
+    }
+    /// Parse an argument of a filter: a literal between double quotes or a number.
+    fn parse_arg(&mut self) -> Option<Arg> {
+        if self.peek() == Some('"') {
+            return self.parse_literal().map(Arg::String);
+        }
+        let mut number = String::new();
+        while let Some(c) = self
+            .peek()
+            .filter(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+        {
+            self.next();
+            number.push(c);
+        }
+        number.parse().ok().map(Arg::Number)
+    }
+    /// Parse a key up to the end of the tag or up to a `??` or `|` operator.
+    fn parse_key(&mut self) -> String {
+        let mut key = String::new();
+        while let Some(next) = self.peek() {
+            let rest = &self.source[self.position..];
+            let close = self.delimiters.nested_close();
+            if next == close
+                || next == '|'
+                || rest.starts_with("??")
+                || rest
+                    .strip_prefix('-')
+                    .is_some_and(|rest| rest.starts_with(close))
+            {
+                break;
+            }
+            self.next();
+            if next == '\\' {
+                match self.peek() {
+                    Some(escaped @ ('*' | '&' | '?' | '|' | '-' | '\\')) => {
+                        self.next();
+                        key.push(escaped);
+                    }
+                    Some(escaped)
+                        if escaped == self.delimiters.nested_open() || escaped == close =>
+                    {
+                        self.next();
+                        key.push(escaped);
+                    }
+                    _ => key.push('\\'),
+                }
+            } else {
+                key.push(next);
+            }
+        }
+        key
+    }
+    /// Parse a literal between double quotes, where `\"` and `\\` are escaped.
+    /// Returns `None` when the literal is never closed.
+    fn parse_literal(&mut self) -> Option<String> {
+        self.next();
+        let mut literal = String::new();
+        loop {
+            match self.next()? {
+                '"' => return Some(literal),
+                '\\' => match self.peek() {
+                    Some(escaped @ ('"' | '\\')) => {
+                        self.next();
+                        literal.push(escaped);
+                    }
+                    _ => literal.push('\\'),
+                },
+                c => literal.push(c),
+            }
+        }
+    }
+    /// Skip the rest of a malformed tag, up to its closing delimiter.
+    fn invalid_tag(&mut self, start: Span, nested: bool) -> StringTemplaterError {
+        let closing = self.delimiters.closing(nested);
+        while self.peek().is_some() && !self.eat_str(&closing) {
+            self.next();
+        }
+        let span = self.span_from(start);
+        StringTemplaterError::InvalidTag {
+            tag: self.source[span.start..span.end].to_string(),
+            span,
+            expanded_from: Vec::new(),
+        }
+    }
+}
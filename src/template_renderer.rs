@@ -0,0 +1,436 @@
+use serde_json::Value;
+use std::{borrow::Cow, collections::HashMap, fmt};
+
+use crate::{
+    data_source::DataSource,
+    errors::StringTemplaterError,
+    escaping::Escaping,
+    filters::FilterFailure,
+    limit::Limit,
+    span::Span,
+    suggestions::suggest,
+    template::{EachBlock, Node, Tag},
+    template_parser::{parse_template, parse_template_recovering},
+    value::is_truthy,
+    MissingKeyPolicy, StringTemplaterOptions,
+};
+
+/// Write the nodes of a compiled template using the values of a data source.
+pub(crate) struct Renderer<'a, D: DataSource + ?Sized> {
+    data: &'a D,
+    option: &'a StringTemplaterOptions,
+    /// The spans of the nested template tags being expanded, from the outermost one.
+    expansion: Vec<Span>,
+    /// The keys of the nested templates being expanded, from the outermost one.
+    expanded_keys: Vec<String>,
+    /// The errors met so far, when the rendering goes on after an error.
+    diagnostics: Option<Vec<StringTemplaterError>>,
+    /// The items of the loop blocks being rendered, from the outermost one.
+    scopes: Vec<LoopScope>,
+    /// The escaping of the values, disabled inside the nested templates of a tag opting out of it.
+    escaping: Escaping,
+    /// The number of bytes written so far.
+    written: usize,
+    /// The number of tags rendered so far.
+    substitutions: usize,
+    /// The number of blocks being rendered, including the ones around the nested templates being expanded.
+    block_depth: usize,
+    /// The array lengths of the datas, asked for by the first loop block needing them.
+    array_lens: Option<HashMap<String, usize>>,
+}
+
+/// The current item of a loop block.
+struct LoopScope {
+    /// The name given to the item.
+    variable: String,
+    /// The path of the item inside the datas, such as `items.3`.
+    path: String,
+    index: usize,
+    len: usize,
+}
+
+impl<'a, D: DataSource + ?Sized> Renderer<'a, D> {
+    /// A renderer stopping at the first error.
+    pub(crate) fn new(data: &'a D, option: &'a StringTemplaterOptions) -> Self {
+        Renderer {
+            data,
+            option,
+            expansion: Vec::new(),
+            expanded_keys: Vec::new(),
+            diagnostics: None,
+            scopes: Vec::new(),
+            escaping: option.escaping,
+            written: 0,
+            substitutions: 0,
+            block_depth: 0,
+            array_lens: None,
+        }
+    }
+    /// A renderer recording every error and rendering the faulty tags as best as it can.
+    pub(crate) fn recovering(data: &'a D, option: &'a StringTemplaterOptions) -> Self {
+        Renderer {
+            diagnostics: Some(Vec::new()),
+            ..Self::new(data, option)
+        }
+    }
+    /// The errors recorded by a recovering renderer.
+    pub(crate) fn into_diagnostics(self) -> Vec<StringTemplaterError> {
+        self.diagnostics.unwrap_or_default()
+    }
+    pub(crate) fn render(
+        &mut self,
+        nodes: &[Node],
+        out: &mut dyn fmt::Write,
+    ) -> Result<(), StringTemplaterError> {
+        for node in nodes {
+            match node {
+                Node::Text(text) => self.write(out, text)?,
+                Node::Tag(tag) => self.render_tag(tag, out)?,
+                Node::If(block) => {
+                    self.substitute()?;
+                    let branch = block.branches.iter().find(|branch| {
+                        self.get_value(&branch.key)
+                            .is_some_and(|value| is_truthy(&value))
+                    });
+                    self.block_depth += 1;
+                    let rendered = match branch {
+                        Some(branch) => self.render(&branch.nodes, out),
+                        None => self.render(&block.otherwise, out),
+                    };
+                    self.block_depth -= 1;
+                    rendered?
+                }
+                Node::Each(block) => {
+                    self.substitute()?;
+                    self.block_depth += 1;
+                    let rendered = self.render_each(block, out);
+                    self.block_depth -= 1;
+                    rendered?
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Render the nodes of a loop block once for every item of its array.
+    fn render_each(
+        &mut self,
+        block: &EachBlock,
+        out: &mut dyn fmt::Write,
+    ) -> Result<(), StringTemplaterError> {
+        let path = self.scoped_key(&block.key).into_owned();
+        let len = self.array_len(&path);
+        if len == 0 {
+            return self.render(&block.otherwise, out);
+        }
+        for index in 0..len {
+            self.substitute()?;
+            self.scopes.push(LoopScope {
+                variable: block.variable.clone(),
+                path: format!("{}.{}", path, index),
+                index,
+                len,
+            });
+            let rendered = self.render(&block.nodes, out);
+            self.scopes.pop();
+            rendered?;
+        }
+        Ok(())
+    }
+    /// The number of items of the array at `path`, or `0` when there's none.
+    /// Arrays are given by `get_value`, found in the array lengths of the datas, or looked up one index after another (`items.0`).
+    fn array_len(&mut self, path: &str) -> usize {
+        if let Some(Value::Array(items)) = self.data.get_value(path).as_deref() {
+            return items.len();
+        }
+        let data = self.data;
+        let lens = self.array_lens.get_or_insert_with(|| data.array_lens());
+        let mut len = lens.get(path).copied().unwrap_or(0);
+        while self.data.get(&format!("{}.{}", path, len)).is_some() {
+            len += 1;
+        }
+        len
+    }
+    /// The value of a key, where the variables of the loops being rendered are resolved.
+    fn get(&self, key: &str) -> Option<Cow<'a, str>> {
+        if let Some(scope) = self.scopes.last() {
+            match key {
+                "@index" => return Some(Cow::Owned(scope.index.to_string())),
+                "@first" => return Some(Cow::Owned((scope.index == 0).to_string())),
+                "@last" => return Some(Cow::Owned((scope.index + 1 == scope.len).to_string())),
+                _ => {}
+            }
+        }
+        self.data.get(&self.scoped_key(key))
+    }
+    /// The typed value of a key, where the variables of the loops being rendered are resolved.
+    fn get_value(&self, key: &str) -> Option<Cow<'a, Value>> {
+        if let Some(scope) = self.scopes.last() {
+            match key {
+                "@index" => return Some(Cow::Owned(Value::from(scope.index))),
+                "@first" => return Some(Cow::Owned(Value::Bool(scope.index == 0))),
+                "@last" => return Some(Cow::Owned(Value::Bool(scope.index + 1 == scope.len))),
+                _ => {}
+            }
+        }
+        self.data.get_value(&self.scoped_key(key))
+    }
+    /// The path of a key inside the datas, replacing the variable of the innermost loop it uses by the path of the current item.
+    fn scoped_key<'k>(&self, key: &'k str) -> Cow<'k, str> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(rest) = key.strip_prefix(scope.variable.as_str()) {
+                if rest.is_empty() || rest.starts_with('.') {
+                    return Cow::Owned(format!("{}{}", scope.path, rest));
+                }
+            }
+        }
+        Cow::Borrowed(key)
+    }
+    /// Either record the error or stop the rendering with it. Exceeded limits always stop it.
+    fn report(&mut self, err: StringTemplaterError) -> Result<(), StringTemplaterError> {
+        match &mut self.diagnostics {
+            Some(diagnostics) if !matches!(err, StringTemplaterError::LimitExceeded { .. }) => {
+                diagnostics.push(err);
+                Ok(())
+            }
+            _ => Err(err),
+        }
+    }
+    /// Count a substitution against the limit: a tag, the check of a block or the rendering of a loop item.
+    fn substitute(&mut self) -> Result<(), StringTemplaterError> {
+        self.substitutions += 1;
+        match self
+            .option
+            .max_substitutions
+            .filter(|max| self.substitutions > *max)
+        {
+            Some(max) => Err(StringTemplaterError::LimitExceeded {
+                limit: Limit::Substitutions(max),
+            }),
+            None => Ok(()),
+        }
+    }
+    fn render_tag(
+        &mut self,
+        tag: &Tag,
+        out: &mut dyn fmt::Write,
+    ) -> Result<(), StringTemplaterError> {
+        self.substitute()?;
+        let option = self.option;
+        if let Some(max) = option
+            .max_mirror_depth
+            .filter(|max| tag.mirror_depth > *max)
+        {
+            return Err(StringTemplaterError::LimitExceeded {
+                limit: Limit::MirrorDepth(max),
+            });
+        }
+
+        // Look for the key, then for its fallbacks
+        let mut missing = None;
+        for key in std::iter::once(&tag.key).chain(&tag.fallbacks) {
+            match self.lookup(key, tag.mirror_depth) {
+                Ok(value) if tag.nested && tag.filters.is_empty() => {
+                    return self.render_nested(key, &value, tag, out)
+                }
+                Ok(value) if tag.nested => {
+                    let mut rendered = String::new();
+                    let written = self.written;
+                    self.render_nested(key, &value, tag, &mut rendered)?;
+                    // Only the filtered output counts as written
+                    self.written = written;
+                    // The values of the nested template are already escaped, like without filters
+                    let filtered = self.apply_filters(rendered.into(), tag)?;
+                    return self.write(out, &filtered);
+                }
+                Ok(value) => {
+                    let filtered = self.apply_filters(value, tag)?;
+                    return self.write_value(&filtered, tag, out);
+                }
+                Err(not_found) => {
+                    missing.get_or_insert(not_found);
+                }
+            }
+        }
+        if let Some(default) = &tag.default {
+            let filtered = self.apply_filters(Cow::Borrowed(default), tag)?;
+            return self.write_value(&filtered, tag, out);
+        }
+        let (key, mirrored) = missing.unwrap_or_default();
+        self.missing_key(&key, mirrored.then_some(&tag.key), tag, out)
+    }
+    /// Apply the filters of a tag to a value.
+    /// A filter can't give a value longer than the output limit, so a chain of filters can't grow a value unbounded.
+    fn apply_filters<'v>(
+        &mut self,
+        mut value: Cow<'v, str>,
+        tag: &Tag,
+    ) -> Result<Cow<'v, str>, StringTemplaterError> {
+        for filter in &tag.filters {
+            match self
+                .option
+                .filters
+                .apply(&filter.name, &value, &filter.args)
+            {
+                Ok(filtered) => {
+                    if let Some(max) = self
+                        .option
+                        .max_output_bytes
+                        .filter(|max| filtered.len() > *max)
+                    {
+                        return Err(StringTemplaterError::LimitExceeded {
+                            limit: Limit::OutputBytes(max),
+                        });
+                    }
+                    value = Cow::Owned(filtered)
+                }
+                Err(FilterFailure::Unknown) => {
+                    self.report(StringTemplaterError::UnknownFilter {
+                        name: filter.name.clone(),
+                        span: tag.span,
+                        expanded_from: self.expansion.clone(),
+                    })?
+                }
+                Err(FilterFailure::InvalidArguments) => {
+                    self.report(StringTemplaterError::InvalidFilterArguments {
+                        name: filter.name.clone(),
+                        span: tag.span,
+                        expanded_from: self.expansion.clone(),
+                    })?
+                }
+                Err(FilterFailure::Failed(message)) => {
+                    self.report(StringTemplaterError::FilterError {
+                        name: filter.name.clone(),
+                        message,
+                        span: tag.span,
+                        expanded_from: self.expansion.clone(),
+                    })?
+                }
+            }
+        }
+        Ok(value)
+    }
+    /// Write a value, escaped unless the tag opts out of the escaping.
+    fn write_value(
+        &mut self,
+        value: &str,
+        tag: &Tag,
+        out: &mut dyn fmt::Write,
+    ) -> Result<(), StringTemplaterError> {
+        if tag.unescaped {
+            self.write(out, value)
+        } else {
+            let escaping = self.escaping;
+            self.write(out, &escaping.escape(value))
+        }
+    }
+    /// Write some text, unless it goes beyond the output limit.
+    fn write(&mut self, out: &mut dyn fmt::Write, text: &str) -> Result<(), StringTemplaterError> {
+        self.written += text.len();
+        if let Some(max) = self
+            .option
+            .max_output_bytes
+            .filter(|max| self.written > *max)
+        {
+            return Err(StringTemplaterError::LimitExceeded {
+                limit: Limit::OutputBytes(max),
+            });
+        }
+        out.write_str(text)
+            .map_err(|err| StringTemplaterError::WriteError {
+                kind: std::io::ErrorKind::Other,
+                message: err.to_string(),
+            })
+    }
+    /// Look for the value of a key, applying the mirrors one after another.
+    /// When a key is missing, returns it along with whether it was reached through a mirror.
+    fn lookup(&self, key: &str, mirror_depth: usize) -> Result<Cow<'a, str>, (String, bool)> {
+        let data = self.data;
+        let mut value = match self.get(key) {
+            Some(value) => value,
+            None => return Err((self.scoped_key(key).into_owned(), false)),
+        };
+        for _ in 0..mirror_depth {
+            value = match data.get(&value) {
+                Some(mirrored) => mirrored,
+                None => return Err((value.into_owned(), true)),
+            };
+        }
+        Ok(value)
+    }
+    /// Render the value of a key as a template, unless it's already being expanded or the nesting is too deep.
+    fn render_nested(
+        &mut self,
+        key: &str,
+        template_str: &str,
+        tag: &Tag,
+        out: &mut dyn fmt::Write,
+    ) -> Result<(), StringTemplaterError> {
+        let key = self.scoped_key(key).into_owned();
+        let cycle = self
+            .expanded_keys
+            .iter()
+            .position(|expanded| *expanded == key);
+        if cycle.is_some() || self.expanded_keys.len() >= self.option.max_nesting_depth {
+            let mut chain = self.expanded_keys[cycle.unwrap_or(0)..].to_vec();
+            chain.push(key);
+            return self.report(StringTemplaterError::RecursionLimit {
+                chain,
+                span: tag.span,
+                expanded_from: self.expansion.clone(),
+            });
+        }
+        self.expansion.push(tag.span);
+        self.expanded_keys.push(key);
+        let escaping = if tag.unescaped {
+            std::mem::replace(&mut self.escaping, Escaping::None)
+        } else {
+            self.escaping
+        };
+        let rendered = if self.diagnostics.is_some() {
+            let (nodes, errors) = parse_template_recovering(
+                template_str,
+                &self.option.delimiters,
+                self.block_depth,
+                self.option.max_block_depth,
+            );
+            for err in errors {
+                self.report(err.with_expansion(&self.expansion))?;
+            }
+            self.render(&nodes, out)
+        } else {
+            match parse_template(
+                template_str,
+                &self.option.delimiters,
+                self.block_depth,
+                self.option.max_block_depth,
+            ) {
+                Ok(nodes) => self.render(&nodes, out),
+                Err(err) => Err(err.with_expansion(&self.expansion)),
+            }
+        };
+        self.expansion.pop();
+        self.expanded_keys.pop();
+        self.escaping = escaping;
+        rendered
+    }
+    fn missing_key(
+        &mut self,
+        key: &str,
+        via_mirror_of: Option<&String>,
+        tag: &Tag,
+        out: &mut dyn fmt::Write,
+    ) -> Result<(), StringTemplaterError> {
+        match self.option.missing_key_policy().as_ref() {
+            MissingKeyPolicy::Error => self.report(StringTemplaterError::UnknownField {
+                key: key.to_string(),
+                via_mirror_of: via_mirror_of.cloned(),
+                suggestions: suggest(key, self.data.keys().iter().map(String::as_str)),
+                span: tag.span,
+                expanded_from: self.expansion.clone(),
+            }),
+            MissingKeyPolicy::Remove => Ok(()),
+            MissingKeyPolicy::KeepTag => self.write(out, &tag.source),
+            MissingKeyPolicy::Placeholder(message) => self.write_value(&message(key), tag, out),
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::{
+    delimiters::Delimiters, errors::StringTemplaterError, key_space::KeySpace,
+    suggestions::suggest, template_parser::DEFAULT_MAX_BLOCK_DEPTH,
+    validate_with_delimiters::validate_with_delimiters, Template,
+};
+
+/// Check a template against a sample of the datas it will be rendered with, without rendering it.
+/// Every key referenced by the template that can't exist in the serialized sample is reported as a `StringTemplaterError::UnknownField`.
+/// Array indices are free, and anything below a `null` value, an empty array or an empty object is accepted.
+/// Only the keys written in the tags are checked, the keys reached through mirrors or nested templates depend on the datas.
+/// The keys of conditional and loop blocks are allowed to be missing, since a missing key is simply false or empty.
+pub fn validate<T: ?Sized + Serialize>(
+    template_str: &str,
+    sample: &T,
+) -> Result<(), Vec<StringTemplaterError>> {
+    validate_with_delimiters(template_str, sample, &Delimiters::default())
+}
+
+pub(crate) fn validate_keys(
+    template_str: &str,
+    delimiters: &Delimiters,
+    key_space: &KeySpace,
+    datas: &HashMap<String, String>,
+) -> Result<(), Vec<StringTemplaterError>> {
+    let (template, mut errors) =
+        Template::compile_recovering(template_str, delimiters, DEFAULT_MAX_BLOCK_DEPTH);
+    let mut references = template.referenced_keys().into_iter().peekable();
+    while let Some(reference) = references.next() {
+        // A tag renders as soon as one key of its `??` chain exists
+        let mut exists = reference.optional || key_space.contains(&reference.key);
+        while let Some(fallback) = references.next_if(|next| next.fallback) {
+            exists |= key_space.contains(&fallback.key);
+        }
+        if !exists {
+            errors.push(StringTemplaterError::UnknownField {
+                suggestions: suggest(&reference.key, datas.keys().map(String::as_str)),
+                key: reference.key,
+                via_mirror_of: None,
+                span: reference.span,
+                expanded_from: Vec::new(),
+            });
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        errors.sort_by_key(|err| err.root_span().map(|span| span.start));
+        Err(errors)
+    }
+}
@@ -0,0 +1,16 @@
+use std::collections::HashMap;
+
+use crate::{
+    delimiters::Delimiters, errors::StringTemplaterError,
+    validate_hashmap_with_delimiters::validate_hashmap_with_delimiters,
+};
+
+/// Check a template against a sample hashmap of the datas it will be rendered with, without rendering it.
+/// Every key referenced by the template that can't exist in the hashmap is reported as a `StringTemplaterError::UnknownField`.
+/// Numbers in the paths of the hashmap are taken as array indices, so `items.0.name` accepts `items.7.name` as well.
+pub fn validate_hashmap(
+    template_str: &str,
+    data: &HashMap<String, String>,
+) -> Result<(), Vec<StringTemplaterError>> {
+    validate_hashmap_with_delimiters(template_str, data, &Delimiters::default())
+}
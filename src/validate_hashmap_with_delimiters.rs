@@ -0,0 +1,20 @@
+use std::collections::HashMap;
+
+use crate::{
+    delimiters::Delimiters, errors::StringTemplaterError, key_space::KeySpace,
+    validate::validate_keys,
+};
+
+/// Check a template whose tags use other delimiters than `{{` and `}}` against a sample hashmap of the datas, the same way as `validate_hashmap`.
+pub fn validate_hashmap_with_delimiters(
+    template_str: &str,
+    data: &HashMap<String, String>,
+    delimiters: &Delimiters,
+) -> Result<(), Vec<StringTemplaterError>> {
+    validate_keys(
+        template_str,
+        delimiters,
+        &KeySpace::from_hashmap(data),
+        data,
+    )
+}
@@ -0,0 +1,28 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::{
+    delimiters::Delimiters, errors::StringTemplaterError, key_space::KeySpace,
+    parse_to_hashmap::flatten, validate::validate_keys,
+};
+
+/// Check a template whose tags use other delimiters than `{{` and `}}` against a sample of the datas, the same way as `validate`.
+pub fn validate_with_delimiters<T: ?Sized + Serialize>(
+    template_str: &str,
+    sample: &T,
+    delimiters: &Delimiters,
+) -> Result<(), Vec<StringTemplaterError>> {
+    match serde_json::to_value(sample) {
+        Ok(serialized) => {
+            let mut datas = HashMap::new();
+            flatten("", &serialized, &mut datas);
+            validate_keys(
+                template_str,
+                delimiters,
+                &KeySpace::from_value(&serialized),
+                &datas,
+            )
+        }
+        Err(err) => Err(vec![StringTemplaterError::SerializeError(err.to_string())]),
+    }
+}
@@ -0,0 +1,64 @@
+use std::borrow::Cow;
+
+use serde_json::Value;
+
+/// The text injected in a template for a value: strings as is, anything else as `JSON`.
+pub(crate) fn value_to_str(value: &Value) -> Cow<'_, str> {
+    match value {
+        Value::String(s) => Cow::Borrowed(s),
+        _ => Cow::Owned(value.to_string()),
+    }
+}
+
+/// The typed value of a text written by `value_to_str`: `null`, booleans and numbers are read back, anything else is a string.
+pub(crate) fn value_from_str(value: &str) -> Value {
+    match value {
+        "null" => Value::Null,
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => match value.parse::<serde_json::Number>() {
+            Ok(number) => Value::Number(number),
+            Err(_) => Value::String(value.to_string()),
+        },
+    }
+}
+
+/// Find the value at the end of a dotted path such as `items.2.name`.
+/// Paths are the same as the keys of `parse_to_hashmap`, so object keys containing dots are found as well.
+pub(crate) fn resolve_path<'v>(value: &'v Value, path: &str) -> Option<&'v Value> {
+    match value {
+        Value::Object(obj) => {
+            if let Some(found) = obj.get(path) {
+                return Some(found);
+            }
+            path.match_indices('.').find_map(|(i, _)| {
+                obj.get(&path[..i])
+                    .and_then(|child| resolve_path(child, &path[i + 1..]))
+            })
+        }
+        Value::Array(arr) => {
+            let (index, rest) = match path.split_once('.') {
+                Some((index, rest)) => (index, Some(rest)),
+                None => (path, None),
+            };
+            let child = arr.get(index.parse::<usize>().ok()?)?;
+            match rest {
+                Some(rest) => resolve_path(child, rest),
+                None => Some(child),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Whether a value makes a conditional block render: anything but `null`, `false`, zero, an empty array or object, or a string that is empty, `"false"`, `"0"` or `"null"`.
+pub(crate) fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64() != Some(0.0),
+        Value::String(s) => !matches!(s.as_str(), "" | "false" | "0" | "null"),
+        Value::Array(arr) => !arr.is_empty(),
+        Value::Object(obj) => !obj.is_empty(),
+    }
+}